@@ -0,0 +1,157 @@
+//! Expansion time of synthetic functions thousands of lines long, checked against the budgets of
+//! `benches/budget.toml`.
+//!
+//! Run with `cargo bench -p clone-on-capture-core`, the run fails when the median time of a case
+//! goes over its budget.
+//!
+//! The `Rc` and `Arc` captures that are last uses are only known at the end of the traversal, the
+//! times include the walk removing their clones from the output (`elide_last_uses`). `analyze` is
+//! not measured, it clones the function for its own traversal.
+
+use clone_on_capture_core::{transform, Options};
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+use syn::ItemFn;
+
+const RUNS: usize = 7;
+
+/// Closures side by side, each cloning a few bindings declared right before it.
+fn flat(count: usize) -> String {
+    let mut body = String::new();
+
+    for i in 0..count {
+        writeln!(
+            body,
+            "let name_{i} = format!(\"name {{}}\", {i});
+            let shared_{i}: Arc<Config> = Arc::new(Config::default());
+            let mut count_{i} = {i};
+            let task_{i} = move || {{
+                count_{i} += name_{i}.len();
+                shared_{i}.retries + count_{i}
+            }};
+            tasks.push(Box::new(task_{i}));
+            drop(shared_{i});"
+        )
+        .unwrap();
+    }
+
+    format!("fn flat(mut tasks: Vec<Box<dyn FnMut() -> usize>>) {{\n{body}}}")
+}
+
+/// Closures and async blocks nested `depth` levels deep, each level using the bindings of all the
+/// levels around it.
+fn nested(count: usize, depth: usize) -> String {
+    let mut body = String::new();
+
+    for i in 0..count {
+        let mut open = String::new();
+        let mut close = String::new();
+
+        for level in 0..depth {
+            let uses = (0..=level)
+                .map(|outer| format!("value_{i}_{outer}.len()"))
+                .collect::<Vec<_>>()
+                .join(" + ");
+
+            writeln!(
+                open,
+                "let value_{i}_{level} = String::from(\"{level}\");
+                let total = {uses};
+                spawn(async move {{
+                    let _ = total;"
+            )
+            .unwrap();
+            close.push_str("});\n");
+        }
+
+        writeln!(body, "{{\n{open}{close}}}").unwrap();
+    }
+
+    format!("async fn nested() {{\n{body}}}")
+}
+
+/// Loops whose bodies spawn closures that use the loop bindings, fields and macros.
+fn loops(count: usize) -> String {
+    let mut body = String::new();
+
+    for i in 0..count {
+        writeln!(
+            body,
+            "let state_{i} = State::new();
+            let label_{i} = String::from(\"label\");
+            for item in items.iter() {{
+                let item = item.clone();
+                if item.enabled {{
+                    handles.push(std::thread::spawn(move || {{
+                        log!(\"{{}} {{}} {{:?}}\", label_{i}, item.name, state_{i}.tx);
+                        match item.kind {{
+                            Kind::A => state_{i}.tx.send(item.id).unwrap(),
+                            _ => {{}}
+                        }}
+                    }}));
+                }} else {{
+                    println!(\"{{}}\", label_{i});
+                }}
+            }}"
+        )
+        .unwrap();
+    }
+
+    format!("fn loops(items: Vec<Item>, mut handles: Vec<JoinHandle<()>>) {{\n{body}}}")
+}
+
+/// The median time of `transform` on `source`.
+fn measure(source: &str) -> Duration {
+    let input: ItemFn = syn::parse_str(source).expect("synthetic function should parse");
+    let mut times = Vec::with_capacity(RUNS);
+
+    for _ in 0..RUNS {
+        let input = input.clone();
+        let start = Instant::now();
+        let output =
+            transform(input, Options::default()).expect("synthetic function should expand");
+        times.push(start.elapsed());
+        drop(output);
+    }
+
+    times.sort();
+    times[RUNS / 2]
+}
+
+fn main() {
+    let budget: toml::Table = include_str!("budget.toml")
+        .parse()
+        .expect("budget.toml should be valid TOML");
+
+    let cases = [
+        ("flat", flat(1000)),
+        ("nested", nested(300, 8)),
+        ("loops", loops(300)),
+    ];
+
+    let mut over = vec![];
+
+    for (name, source) in &cases {
+        let lines = source.lines().count();
+        let time = measure(source);
+        let limit = budget
+            .get(*name)
+            .and_then(toml::Value::as_integer)
+            .map(|millis| Duration::from_millis(millis.unsigned_abs()));
+
+        match limit {
+            Some(limit) => {
+                println!("{name:<8} {lines:>6} lines {time:>12.2?} (budget {limit:?})");
+                if time > limit {
+                    over.push(*name);
+                }
+            }
+            None => println!("{name:<8} {lines:>6} lines {time:>12.2?} (no budget)"),
+        }
+    }
+
+    if !over.is_empty() {
+        eprintln!("over budget: {}", over.join(", "));
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::HashSet;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{bracketed, Error, Expr, Ident, Result, Token};
+
+/// The input of `capture!`: a binding list and the closure or async block, like
+/// `[a, b, weak c] move || ...`.
+#[derive(Clone, Debug)]
+pub struct CaptureInput {
+    pub bindings: Vec<CaptureBinding>,
+    pub expr: Expr,
+}
+
+/// A binding of the list, `weak` ones are captured as a weak reference.
+#[derive(Clone, Debug)]
+pub struct CaptureBinding {
+    pub ident: Ident,
+    pub weak: bool,
+}
+
+impl Parse for CaptureBinding {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let ident: Ident = input.parse()?;
+
+        if ident == "weak" && input.peek(Ident) {
+            return Ok(CaptureBinding {
+                ident: input.parse()?,
+                weak: true,
+            });
+        }
+
+        Ok(CaptureBinding { ident, weak: false })
+    }
+}
+
+impl Parse for CaptureInput {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        bracketed!(content in input);
+        let bindings = Punctuated::<CaptureBinding, Token![,]>::parse_terminated(&content)?;
+
+        let mut seen = HashSet::new();
+        for binding in &bindings {
+            if !seen.insert(&binding.ident) {
+                return Err(Error::new(
+                    binding.ident.span(),
+                    format!("`{}` is listed more than once", binding.ident),
+                ));
+            }
+        }
+
+        Ok(CaptureInput {
+            bindings: bindings.into_iter().collect(),
+            expr: input.parse()?,
+        })
+    }
+}
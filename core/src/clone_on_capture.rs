@@ -0,0 +1,2713 @@
+use proc_macro2::{TokenStream, TokenTree};
+use quote::{quote, ToTokens};
+use std::collections::{HashMap, HashSet};
+use syn::ext::IdentExt;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::visit::Visit;
+use syn::visit_mut::{self, VisitMut};
+use syn::{
+    parse_quote, parse_quote_spanned, Attribute, BinOp, Block, Error, Expr, ExprAssign, ExprAsync,
+    ExprBinary, ExprBlock, ExprCall, ExprClosure, ExprField, ExprForLoop, ExprIf, ExprIndex,
+    ExprLoop, ExprMacro, ExprMatch, ExprMethodCall, ExprPath, ExprReference, ExprStruct, ExprUnary,
+    ExprWhile, FnArg, Ident, Item, ItemFn, Local, Macro, Member, Meta, Pat, PatType, Path, Result,
+    Stmt, Token, Type, TypePath, UnOp,
+};
+
+use crate::capture_input::CaptureInput;
+use crate::config;
+use crate::debug::{self, location};
+use crate::diagnostics::{Lint, Warning};
+use crate::manifest::{self, Record};
+use crate::name::Name;
+use crate::options::{Options, Target};
+use crate::report::{CaptureEntry, CaptureReport, ClosureReport};
+use crate::strategy::Strategy;
+
+/// Records a line of the scope trace, indented by the current nesting level.
+macro_rules! trace {
+    ($data:expr, $($arg:tt)*) => {{
+        let depth = $data.nested.len();
+        if let Some(trace) = &mut $data.trace {
+            trace.push(format!("{}{}", "  ".repeat(depth), format!($($arg)*)));
+        }
+    }};
+}
+
+/// Why a binding is moved into move closures instead of being cloned.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Skip {
+    /// The binding name starts with the skip prefix, `dc_` unless configured.
+    Prefix(String),
+    /// The binding is declared `mut`.
+    Mut,
+    /// The declaration is marked `#[no_clone]`.
+    NoClone,
+    /// The binding is listed in the `skip(...)` argument.
+    Listed,
+    /// The binding is not listed in the `only(...)` argument.
+    NotOnly,
+}
+
+impl Skip {
+    pub fn reason(&self) -> String {
+        match self {
+            Skip::Prefix(prefix) => format!("{prefix} prefix"),
+            Skip::Mut => "mut binding".to_string(),
+            Skip::NoClone => "#[no_clone]".to_string(),
+            Skip::Listed => "listed in `skip(...)`".to_string(),
+            Skip::NotOnly => "not listed in `only(...)`".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct Binding {
+    /// Distinguishes bindings that share a name, assigned when the binding is declared.
+    pub id: usize,
+    /// Type annotation seen at the declaration.
+    pub ty: Option<Type>,
+    /// How the binding is captured unless it is skipped, chosen from its type annotation.
+    pub strategy: Strategy,
+    /// `Strategy::Arc` or `Strategy::Rc` when `#[shared]` wrapped the declaration in a pointer.
+    pub shared: Option<Strategy>,
+    /// The pointer holds a `RefCell` or `Mutex` added by `#[shared_mut]`, uses of the binding are
+    /// rewritten to borrow or lock it.
+    pub cell: bool,
+    pub skip: Option<Skip>,
+}
+
+/// A field path captured by cloning only that place, like `state.tx`.
+#[derive(Clone, Debug, PartialEq)]
+struct Place {
+    /// Fresh binding the uses inside the closure are rewritten to.
+    pub ident: Ident,
+    /// The field expression the place is cloned from.
+    pub expr: Expr,
+    pub binding: Binding,
+}
+
+/// A binding a promoted closure keeps borrowed instead of cloning it.
+#[derive(Clone, Debug, PartialEq)]
+struct Borrow {
+    /// Fresh binding holding the reference, uses are rewritten to `(*ident)`.
+    pub ident: Ident,
+    pub binding: Binding,
+}
+
+#[derive(Clone, Default, Debug, PartialEq)]
+struct NestBlock {
+    pub idents: HashMap<Name, Binding>,
+    /// Ids of the bindings used inside the block, the key is the first use.
+    pub usage: HashMap<Name, usize>,
+    /// Ids of the reference counted bindings the block holds the last use of, moved instead of
+    /// cloned.
+    pub last_use: HashSet<usize>,
+    /// Ids of the reference counted bindings the block may hold the last use of, which is only
+    /// known once the function has been traversed. Their clones are marked to be removed then.
+    pub maybe_last_use: HashSet<usize>,
+    /// Field paths used inside the block, keyed by their dotted path.
+    pub places: HashMap<String, Place>,
+    /// Skipped bindings used inside a promoted block, keyed by the original binding.
+    pub borrowed: HashMap<Name, Borrow>,
+    /// Usages that were only seen by scanning macro tokens.
+    pub scanned: HashSet<Name>,
+    pub capture: bool,
+    /// The block was not `move` in the source and was promoted by the macro.
+    pub promoted: bool,
+    /// The block is `move` but left as written because it is not marked in `opt_in` mode.
+    pub opted_out: bool,
+    /// Captures must be `Send + 'static`.
+    pub send: bool,
+    /// The block is a loop body and runs once per iteration.
+    pub looping: bool,
+    /// The block is a closure or async block, which may run more than once.
+    pub closure: bool,
+}
+
+impl NestBlock {
+    /// The bindings used inside the block, with the strategy the block captures each of them with.
+    fn used<'a, 'b>(
+        &'a self,
+        bindings: &'b HashMap<usize, Binding>,
+    ) -> Vec<(&'a Name, &'b Binding, Strategy)> {
+        self.usage
+            .iter()
+            .map(|(name, id)| {
+                let binding = &bindings[id];
+                let strategy = if self.last_use.contains(id) {
+                    Strategy::LastUse
+                } else {
+                    binding.strategy.clone()
+                };
+                (name, binding, strategy)
+            })
+            .collect()
+    }
+}
+
+/// A binding cloned or moved into a closure, checked for later uses by the lints.
+#[derive(Clone, Debug)]
+struct CaptureSite {
+    pub id: usize,
+    /// The closure or async block, numbered in traversal order.
+    pub capture: usize,
+    pub strategy: Strategy,
+    /// The first use of the binding inside the closure.
+    pub ident: Ident,
+    pub skip: Option<Skip>,
+    /// The binding was used outside of a cloning capture before the closure, a borrow of it may
+    /// still be alive.
+    pub used_before: bool,
+    /// The first use of the binding after the closure.
+    pub used_after: Option<proc_macro2::Span>,
+    /// The closure only references the binding inside macro tokens, reported unless the capture
+    /// turns out to be a last use.
+    pub scanned: bool,
+}
+
+/// A closure that may hold the last use of a reference counted binding, its report, explanation,
+/// manifest record and trace are updated when it does.
+#[derive(Clone, Debug)]
+struct PendingClosure {
+    pub kind: &'static str,
+    pub span: proc_macro2::Span,
+    pub nest_block: NestBlock,
+    /// The closure or async block, numbered in traversal order.
+    pub capture: usize,
+    /// Index of the first line describing the captures in the trace, and its depth.
+    pub trace: (usize, usize),
+    /// Indices of the outputs of the closure in `report`, `warnings` and `manifest`.
+    pub report: Option<usize>,
+    pub explain: Option<usize>,
+    pub record: Option<usize>,
+}
+
+/// How an expression is replaced once its subexpressions have been traversed.
+enum Rewrite {
+    /// By another expression, like a field path by the binding holding its clone.
+    Replace(Expr),
+    /// By the closure or async block a `cc!` marker wraps, which is traversed in its place.
+    Unwrap(Expr),
+    /// By a block evaluating the value before borrowing the `#[shared_mut]` place.
+    CellAssignment,
+    /// By a block ending the borrow or lock of a method call chain on a `#[shared_mut]` binding,
+    /// after evaluating the arguments first when set.
+    CellCall(bool),
+    /// By a block cloning the captures of the closure or async block first.
+    Captured(NestBlock, &'static str),
+}
+
+impl Rewrite {
+    fn apply(self, data: &mut Data, expr: &mut Expr) -> Result<()> {
+        let taken = std::mem::replace(expr, Expr::Verbatim(TokenStream::new()));
+
+        *expr = match self {
+            Rewrite::Replace(replacement) => replacement,
+            Rewrite::Unwrap(mut unwrapped) => {
+                parse_generic_expression(data, &mut unwrapped)?;
+                unwrapped
+            }
+            Rewrite::CellAssignment => cell_assignment(taken),
+            Rewrite::CellCall(arguments_first) => cell_call(taken, arguments_first),
+            Rewrite::Captured(nest_block, kind) => {
+                captured_expression(data, nest_block, kind, taken)?
+            }
+        };
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+struct Data {
+    pub explain: bool,
+    pub send: bool,
+    pub cheap_only: bool,
+    pub promote: bool,
+    pub opt_in: bool,
+    pub targets: Vec<Target>,
+    pub propagate: Option<Path>,
+    /// Bindings of the `shared(...)` and `shared_local(...)` arguments.
+    pub shared: Vec<(Ident, Strategy)>,
+    /// Names of the `shared` arguments a declaration was found for.
+    pub shared_declared: HashSet<Ident>,
+    /// Bindings of the `skip(...)` argument.
+    pub skip: Vec<Ident>,
+    /// Bindings of the `only(...)` argument, `None` to clone all bindings.
+    pub only: Option<Vec<Ident>>,
+    /// Names of the `skip` and `only` arguments a binding was found for.
+    pub listed_declared: HashSet<Ident>,
+    /// The next path expression is the root of a place that is written to.
+    pub writing: bool,
+    /// The next method call is the receiver of another one, the outer call ends the borrow.
+    pub chained: bool,
+    pub lints: HashSet<Lint>,
+    pub types: Vec<(Path, Strategy)>,
+    /// Bindings starting with it are skipped, empty to skip none.
+    pub skip_prefix: String,
+    /// Skip `mut` bindings.
+    pub move_mut: bool,
+    /// Strategy of bindings their type does not choose one for.
+    pub strategy: Strategy,
+    /// Macros whose arguments are traversed as expressions.
+    pub macros: Vec<Ident>,
+    /// Fresh bindings of the field paths captured so far, by dotted path. They are numbered, as
+    /// joining the members alone names `a.b_c` and `a_b.c` the same.
+    pub place_idents: HashMap<String, Ident>,
+    pub warnings: Vec<Warning>,
+    pub sites: Vec<CaptureSite>,
+    /// Indices into `sites` of the captures of each binding id not used after their closure yet.
+    pub unused_sites: HashMap<usize, Vec<usize>>,
+    /// Number of move closures and async blocks seen so far.
+    pub captures: usize,
+    /// `Rc` and `Arc` captures that are the last use of the binding move it instead of cloning it,
+    /// off for `capture!`, which cannot see the later uses.
+    pub last_uses: bool,
+    /// Closures with captures that may be last uses, in traversal order.
+    pub pending: Vec<PendingClosure>,
+    /// Number of clones marked as possible last uses in the output.
+    pub marked: usize,
+    /// Ids of the bindings used outside of a cloning capture so far, like by a reference or a
+    /// closure that is not `move`.
+    pub used_directly: HashSet<usize>,
+    pub next_id: usize,
+    /// Every binding declared so far by id, uses of a binding only record its id.
+    pub bindings: HashMap<usize, Binding>,
+    pub trace: Option<Vec<String>>,
+    pub manifest: Option<Vec<Record>>,
+    pub report: Option<Vec<ClosureReport>>,
+    pub function: Vec<Ident>,
+    /// The `self` receiver of the function, only captured through field paths.
+    pub receiver: Option<Binding>,
+    pub root: HashMap<Name, Binding>,
+    pub nested: Vec<NestBlock>,
+    /// Generated clones are reported to `runtime::trace::record`.
+    pub trace_clones: bool,
+    /// The output is discarded, closures are analyzed but not rewritten.
+    pub analysis_only: bool,
+    /// The first error of the traversal, until the caller of the `VisitMut` method takes it.
+    pub error: Option<Error>,
+}
+
+impl Data {
+    pub fn new(options: &Options) -> Self {
+        Data {
+            explain: options.explain,
+            send: options.send,
+            cheap_only: options.cheap_only,
+            promote: options.promote,
+            opt_in: options.opt_in || !options.targets.is_empty(),
+            targets: options.targets.clone(),
+            propagate: options.propagate.clone(),
+            shared: options.shared.clone(),
+            lints: options.lints.clone(),
+            types: options.types.clone(),
+            skip_prefix: options
+                .skip_prefix
+                .clone()
+                .unwrap_or_else(|| "dc_".to_string()),
+            move_mut: !options.clone_mut,
+            strategy: options.strategy.clone(),
+            macros: options.macros.clone(),
+            skip: options.skip.clone(),
+            only: options.only.clone(),
+            trace_clones: options.trace_clones,
+            ..Default::default()
+        }
+    }
+
+    pub fn push_nested_block(&mut self, capture: bool) {
+        self.nested.push(NestBlock {
+            capture,
+            ..Default::default()
+        });
+    }
+
+    /// Marks the innermost block as requiring `Send + 'static` captures.
+    pub fn mark_send(&mut self, send: bool) {
+        let send = self.send || send;
+        if let Some(nested_block) = self.nested.last_mut() {
+            nested_block.send = send;
+        }
+    }
+
+    pub fn mark_promoted(&mut self, promoted: bool) {
+        if let Some(nested_block) = self.nested.last_mut() {
+            nested_block.promoted = promoted;
+        }
+    }
+
+    pub fn mark_closure(&mut self) {
+        if let Some(nested_block) = self.nested.last_mut() {
+            nested_block.closure = true;
+        }
+    }
+
+    pub fn mark_opted_out(&mut self, opted_out: bool) {
+        if let Some(nested_block) = self.nested.last_mut() {
+            nested_block.opted_out = opted_out;
+        }
+    }
+
+    pub fn push_loop_block(&mut self) {
+        self.nested.push(NestBlock {
+            looping: true,
+            ..Default::default()
+        });
+    }
+
+    /// Declares the bindings of a pattern, they satisfy the `skip` and `only` lists.
+    pub fn push_idents(&mut self, other: HashMap<Ident, Binding>) {
+        for ident in other.keys() {
+            if self.skip.contains(ident)
+                || self.only.as_ref().is_some_and(|only| only.contains(ident))
+            {
+                self.listed_declared.insert(ident.clone());
+            }
+        }
+
+        self.push_members(other);
+    }
+
+    /// Records names that are scoped like bindings but not declared by a pattern, like the
+    /// members of a struct literal.
+    pub fn push_members(&mut self, other: HashMap<Ident, Binding>) {
+        if other.is_empty() {
+            return;
+        }
+
+        trace!(self, "bindings: {}", Self::string_idents(other.keys()));
+
+        let mut other: HashMap<Name, Binding> = other
+            .into_iter()
+            .map(|(ident, binding)| (Name::new(ident), binding))
+            .collect();
+
+        for binding in other.values_mut() {
+            self.next_id += 1;
+            binding.id = self.next_id;
+            binding.strategy = match &binding.shared {
+                Some(pointer) => pointer.clone(),
+                None => Strategy::for_type(binding.ty.as_ref(), &self.types, &self.strategy),
+            };
+            self.bindings.insert(binding.id, binding.clone());
+        }
+
+        match self.nested.last_mut() {
+            Some(nested_block) => nested_block.idents.extend(other),
+            None => self.root.extend(other),
+        }
+    }
+
+    pub fn string_idents<T: std::fmt::Display>(idents: impl IntoIterator<Item = T>) -> String {
+        let mut idents = idents
+            .into_iter()
+            .map(|ident| format!("`{ident}`"))
+            .collect::<Vec<_>>();
+        idents.sort();
+        idents.join(", ")
+    }
+
+    /// Finds the binding `name` refers to and the nesting level it was declared at, `None` for
+    /// the fn body.
+    fn resolve(&self, name: &Name) -> Option<(Option<usize>, &Binding)> {
+        self.nested
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(offset, nest)| nest.idents.get(name).map(|binding| (Some(offset), binding)))
+            .or_else(|| self.root.get(name).map(|binding| (None, binding)))
+    }
+
+    fn push_site(&mut self, site: CaptureSite) {
+        self.unused_sites
+            .entry(site.id)
+            .or_default()
+            .push(self.sites.len());
+        self.sites.push(site);
+    }
+
+    fn mark_used(&mut self, id: usize, span: proc_macro2::Span) {
+        for index in self.unused_sites.remove(&id).unwrap_or_default() {
+            self.sites[index].used_after = Some(span);
+        }
+    }
+
+    /// The error a `VisitMut` method recorded, since they cannot return it.
+    pub fn visit_result(&mut self) -> Result<()> {
+        self.error.take().map_or(Ok(()), Err)
+    }
+
+    /// Records a use of `other`, `scanned` when it was only found by scanning macro tokens.
+    pub fn push_usage(&mut self, other: Name, scanned: bool) -> Result<()> {
+        let (nested, id) = match self.resolve(&other) {
+            Some((nested, binding)) => (nested, binding.id),
+            None => return Ok(()),
+        };
+
+        match nested {
+            None if self.nested.is_empty() => {}
+            None => trace!(self, "use of `{other}` (declared in fn body)"),
+            Some(offset) => trace!(self, "use of `{other}` (declared at level {})", offset + 1),
+        }
+
+        self.mark_used(id, other.ident().span());
+
+        let declared = nested.map_or(0, |offset| offset + 1);
+        if !self.nested[declared..].iter().any(|nest| nest.capture) {
+            self.used_directly.insert(id);
+        }
+
+        let capturing = |nest: &NestBlock| nested.is_none() || nest.capture;
+
+        for nest in self.nested.iter_mut().skip(declared) {
+            if capturing(nest) {
+                if scanned && !nest.usage.contains_key(&other) {
+                    nest.scanned.insert(other.clone());
+                } else if !scanned {
+                    nest.scanned.remove(&other);
+                }
+                nest.usage.insert(other.clone(), id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records a use of the field path `expr` inside a move closure, returns the fresh binding
+    /// the use is rewritten to, or `None` when the base binding is used as a whole instead, like
+    /// when it is not annotated with a value type.
+    pub fn push_place(&mut self, expr: &ExprField) -> Option<Ident> {
+        let (root, members) = field_path(expr)?;
+
+        let (nested, binding) = if root == "self" {
+            (None, self.receiver.clone()?)
+        } else {
+            let (nested, binding) = self.resolve(&Name::from(&root))?;
+            (nested, binding.clone())
+        };
+
+        // Through a pointer the field is shared with the other handles, the base is cloned.
+        if binding.skip.is_some()
+            || binding.strategy != Strategy::Capture
+            || (root != "self"
+                && (binding.shared.is_some() || !is_plain_value(binding.ty.as_ref())))
+        {
+            return None;
+        }
+
+        let key = std::iter::once(root.unraw().to_string())
+            .chain(members)
+            .collect::<Vec<_>>();
+
+        self.push_captured_place(key, Expr::Field(expr.clone()), nested, binding, root.span())
+    }
+
+    /// Records a use of the whole `self` receiver inside a move closure, returns the fresh binding
+    /// the use is rewritten to. `self` cannot be rebound by a `let`, so it is cloned like a place.
+    ///
+    /// Shared and mutable references are left as written, like `mut self` when `mut` bindings
+    /// are moved.
+    pub fn push_receiver(&mut self, expr: &ExprPath) -> Option<Ident> {
+        let binding = self.receiver.clone()?;
+        let strategy = Strategy::for_type(binding.ty.as_ref(), &self.types, &self.strategy);
+
+        if binding.skip.is_some() || !strategy.clones() {
+            return None;
+        }
+
+        let span = expr.span();
+        self.push_captured_place(
+            vec!["self".to_string()],
+            Expr::Path(expr.clone()),
+            None,
+            binding,
+            span,
+        )
+    }
+
+    /// Records a use of the place `expr` of `binding`, declared at the level `nested`, inside the
+    /// move closures after its declaration. Returns the fresh binding the use is rewritten to.
+    fn push_captured_place(
+        &mut self,
+        key: Vec<String>,
+        expr: Expr,
+        nested: Option<usize>,
+        binding: Binding,
+        span: proc_macro2::Span,
+    ) -> Option<Ident> {
+        let declared = nested.map_or(0, |offset| offset + 1);
+
+        if !self.nested.iter().skip(declared).any(|nest| nest.capture) {
+            return None;
+        }
+
+        let number = self.place_idents.len() + 1;
+        let ident = self
+            .place_idents
+            .entry(key.join("."))
+            .or_insert_with(|| {
+                Ident::new(
+                    &format!("captured_{}_{number}", key.join("_")),
+                    proc_macro2::Span::mixed_site(),
+                )
+            })
+            .clone();
+        let key = key.join(".");
+
+        trace!(self, "use of `{key}` (field path)");
+        self.mark_used(binding.id, span);
+
+        let place = Place {
+            ident: ident.clone(),
+            expr,
+            binding,
+        };
+
+        for nest in self.nested.iter_mut().skip(declared) {
+            if nest.capture {
+                nest.places.insert(key.clone(), place.clone());
+            }
+        }
+
+        Some(ident)
+    }
+
+    /// The declaration level and binding of `name` when a use at the current level stays borrowed
+    /// because a promoted block between the declaration and the use does not clone it.
+    fn borrowing(&self, name: &Name) -> Option<(usize, Binding)> {
+        let (nested, binding) = self.resolve(name)?;
+        let declared = nested.map_or(0, |offset| offset + 1);
+
+        binding.skip.as_ref()?;
+
+        self.nested
+            .iter()
+            .skip(declared)
+            .any(|nest| nest.promoted)
+            .then(|| (declared, binding.clone()))
+    }
+
+    /// Records a use of `name` that stays borrowed, returns the binding holding the reference.
+    pub fn push_borrowed(&mut self, name: &Name) -> Option<Ident> {
+        let (declared, binding) = self.borrowing(name)?;
+        let ident = name.ident();
+        let borrow = Borrow {
+            ident: Ident::new(
+                &format!("borrowed_{}", ident.unraw()),
+                proc_macro2::Span::mixed_site(),
+            ),
+            binding,
+        };
+
+        trace!(self, "use of `{ident}` (stays borrowed)");
+        self.mark_used(borrow.binding.id, ident.span());
+
+        for nest in self.nested.iter_mut().skip(declared) {
+            if nest.promoted {
+                nest.borrowed.insert(name.clone(), borrow.clone());
+            }
+        }
+
+        Some(borrow.ident)
+    }
+
+    /// Whether a closure at the current level that uses `name` runs it through a loop body the
+    /// binding was declared outside of.
+    pub fn in_loop(&self, name: &Name) -> bool {
+        self.since_declared(name).any(|nest| nest.looping)
+    }
+
+    /// Whether a closure at the current level that uses `name` is nested in a closure or async
+    /// block the binding was declared outside of, so moving it would make that one `FnOnce`.
+    pub fn in_closure(&self, name: &Name) -> bool {
+        self.since_declared(name).any(|nest| nest.closure)
+    }
+
+    /// The levels between the declaration of `name` and the current level.
+    fn since_declared(&self, name: &Name) -> impl Iterator<Item = &NestBlock> {
+        let declared = self
+            .nested
+            .iter()
+            .rposition(|nest| nest.idents.contains_key(name))
+            .map_or(0, |offset| offset + 1);
+
+        self.nested.iter().skip(declared)
+    }
+
+    pub fn warn(&mut self, lint: Lint, span: proc_macro2::Span, message: String) {
+        if self.lints.contains(&lint) {
+            self.warnings.push(Warning::lint(lint, span, message));
+        }
+    }
+
+    /// Appends the manifest record of a closure, returns its index.
+    pub fn record(
+        &mut self,
+        kind: &'static str,
+        span: proc_macro2::Span,
+        nest_block: &NestBlock,
+    ) -> Option<usize> {
+        self.manifest.as_ref()?;
+
+        let record = self.manifest_record(kind, span, nest_block);
+        let manifest = self.manifest.as_mut()?;
+        manifest.push(record);
+        Some(manifest.len() - 1)
+    }
+
+    fn manifest_record(
+        &self,
+        kind: &'static str,
+        span: proc_macro2::Span,
+        nest_block: &NestBlock,
+    ) -> Record {
+        let places = nest_block.places.keys().map(|key| (key.clone(), "clone"));
+        let borrowed = nest_block
+            .borrowed
+            .keys()
+            .map(|ident| (ident.to_string(), "borrow"));
+
+        Record::new(
+            &self.function,
+            kind,
+            span,
+            nest_block.used(&self.bindings),
+            places.chain(borrowed),
+        )
+    }
+
+    pub fn pop_nested_block(&mut self) -> Result<Option<NestBlock>> {
+        if let Some(nested_block) = self.nested.pop() {
+            return Ok(Some(nested_block));
+        }
+
+        Err(Error::new(
+            proc_macro2::Span::call_site(),
+            "no nested level",
+        ))
+    }
+}
+
+/// The function body is traversed in place: statements and expressions go through
+/// `parse_generic_statement` and `parse_generic_expression`, which open and close scopes on
+/// `nested` around their children.
+impl VisitMut for Data {
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        if self.error.is_none() {
+            if let Err(error) = parse_generic_statement(self, stmt) {
+                self.error = Some(error);
+            }
+        }
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        if self.error.is_none() {
+            if let Err(error) = parse_generic_expression(self, expr) {
+                self.error = Some(error);
+            }
+        }
+    }
+
+    // Bindings are taken from patterns by `extract_pat`, and types and attributes cannot use
+    // them.
+    fn visit_pat_mut(&mut self, _: &mut Pat) {}
+
+    fn visit_type_mut(&mut self, _: &mut Type) {}
+
+    fn visit_attribute_mut(&mut self, _: &mut Attribute) {}
+}
+
+/// Reports what the closures and async blocks of `input` capture with the default options.
+///
+/// The analysis stops at the first error the attribute would report, the report then only has the
+/// closures before it.
+pub fn analyze(input: &ItemFn) -> CaptureReport {
+    let options = Options::default();
+    let mut input = input.clone();
+
+    let mut data = Data {
+        last_uses: true,
+        report: Some(vec![]),
+        function: vec![input.sig.ident.clone()],
+        analysis_only: true,
+        ..Data::new(&options)
+    };
+
+    // The later uses of the bindings are only known when the whole function was traversed.
+    if parse_function(&mut data, &mut input).is_ok() {
+        elide_last_uses(&mut data, None);
+    }
+
+    CaptureReport {
+        closures: data.report.unwrap_or_default(),
+    }
+}
+
+/// Rewrites the closures and async blocks of `input` to clone their captures, like the attribute
+/// with `options` as arguments.
+pub fn transform(mut input: ItemFn, options: Options) -> Result<ItemFn> {
+    let before = options.dump.as_ref().map(|_| input.clone());
+
+    let mut data = Data {
+        last_uses: true,
+        trace: options.dump.as_ref().map(|_| vec![]),
+        manifest: options.manifest.as_ref().map(|_| vec![]),
+        function: vec![input.sig.ident.clone()],
+        ..Data::new(&options)
+    };
+
+    parse_function(&mut data, &mut input)?;
+
+    for (name, _) in &data.shared {
+        if !data.shared_declared.contains(name) {
+            return Err(Error::new(
+                name.span(),
+                format!("`{name}` is listed as shared but no `let {name} = ...` declares it"),
+            ));
+        }
+    }
+
+    let mut unmatched = data
+        .skip
+        .iter()
+        .chain(data.only.iter().flatten())
+        .filter(|name| !data.listed_declared.contains(*name))
+        .map(|name| {
+            Error::new(
+                name.span(),
+                format!("`{name}` is listed but the function has no binding with that name"),
+            )
+        });
+
+    if let Some(mut error) = unmatched.next() {
+        unmatched.for_each(|other| error.combine(other));
+        return Err(error);
+    }
+
+    let elided = elide_last_uses(&mut data, Some(&mut input.block));
+
+    for site in std::mem::take(&mut data.sites) {
+        if elided.contains(&(site.capture, site.id)) {
+            continue;
+        }
+
+        if site.scanned {
+            data.warn(
+                Lint::MacroOnlyCapture,
+                site.ident.span(),
+                format!(
+                    "`{}` is only referenced inside macro tokens, it may be cloned without being used",
+                    site.ident
+                ),
+            );
+        }
+
+        match (site.skip, site.used_after) {
+            (None, None) if !site.used_before => data.warn(
+                Lint::RedundantClone,
+                site.ident.span(),
+                format!(
+                    "`{}` is cloned into the closure but not used after it, the clone is redundant",
+                    site.ident
+                ),
+            ),
+            (Some(skip @ Skip::Prefix(_)), Some(span)) => data.warn(
+                Lint::MovedDcBinding,
+                span,
+                format!(
+                    "`{}` is used after a move closure took it without cloning ({})",
+                    site.ident,
+                    skip.reason()
+                ),
+            ),
+            _ => {}
+        }
+    }
+
+    let warnings = data.warnings.iter().map(Warning::to_stmt);
+    input.block.stmts.splice(0..0, warnings);
+
+    if let Some(config) = &options.config {
+        input.block.stmts.insert(0, config::dependency(config));
+    }
+
+    if let (Some(dir), Some(before), Some(trace)) = (&options.dump, &before, &data.trace) {
+        debug::write_dump(dir, before, &input, trace)?;
+    }
+
+    if let (Some(path), Some(records)) = (&options.manifest, &data.manifest) {
+        manifest::append(path, records)?;
+    }
+
+    Ok(input)
+}
+
+/// Rewrites a single `move` closure or async block to clone the bindings of the list, the
+/// expansion of `capture!`, which passes the options of the crate configuration.
+pub fn capture(input: CaptureInput, options: Options) -> Result<Expr> {
+    match &input.expr {
+        Expr::Closure(expr_closure) if expr_closure.capture.is_some() => {}
+        Expr::Async(expr_async) if expr_async.capture.is_some() => {}
+        expr => {
+            return Err(Error::new_spanned(
+                expr,
+                "expected a `move` closure or an `async move` block",
+            ))
+        }
+    }
+
+    let mut data = Data {
+        report: Some(vec![]),
+        ..Data::new(&options)
+    };
+
+    for binding in &input.bindings {
+        data.push_idents(HashMap::from([(binding.ident.clone(), Binding::default())]));
+
+        if binding.weak {
+            if let Some(root) = data.root.get_mut(&Name::from(&binding.ident)) {
+                root.strategy = Strategy::Weak;
+                data.bindings.insert(root.id, root.clone());
+            }
+        }
+    }
+
+    let mut expr = input.expr;
+    parse_generic_expression(&mut data, &mut expr)?;
+
+    // Field paths are reported as `a.b`, they still reference `a`.
+    let used: HashSet<String> = data
+        .report
+        .iter()
+        .flatten()
+        .flat_map(|closure| &closure.captures)
+        .map(|capture| {
+            capture
+                .name
+                .split('.')
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        })
+        .collect();
+
+    let mut errors = input
+        .bindings
+        .iter()
+        .filter(|binding| !used.contains(&binding.ident.to_string()))
+        .map(|binding| {
+            Error::new(
+                binding.ident.span(),
+                format!(
+                    "`{}` is listed but the closure never references it",
+                    binding.ident
+                ),
+            )
+        });
+
+    if let Some(mut error) = errors.next() {
+        errors.for_each(|other| error.combine(other));
+        return Err(error);
+    }
+
+    Ok(match &options.config {
+        Some(config) => {
+            let dependency = config::dependency(config);
+            parse_quote!({
+                #dependency
+                #expr
+            })
+        }
+        None => expr,
+    })
+}
+
+fn parse_function(data: &mut Data, input: &mut ItemFn) -> Result<()> {
+    for arg in &input.sig.inputs {
+        match arg {
+            FnArg::Receiver(receiver) => {
+                data.next_id += 1;
+                data.receiver = Some(Binding {
+                    id: data.next_id,
+                    ty: Some((*receiver.ty).clone()),
+                    // `mut self`, `&mut self` is a reference the closure moves.
+                    skip: (receiver.reference.is_none()
+                        && receiver.mutability.is_some()
+                        && data.move_mut)
+                        .then_some(Skip::Mut),
+                    ..Default::default()
+                });
+            }
+            FnArg::Typed(pat_type) => {
+                let bindings = extract_pat_type(data, pat_type)?;
+                data.push_idents(bindings);
+            }
+        }
+    }
+
+    data.visit_block_mut(&mut input.block);
+    data.visit_result()
+}
+
+/// Moves the `Rc` and `Arc` captures that are not followed by another use of the binding, moving
+/// those is indistinguishable from cloning them. Removes their clones from `block` and updates
+/// the outputs of their closures, returns the elided captures by closure and binding id.
+fn elide_last_uses(data: &mut Data, block: Option<&mut Block>) -> HashSet<(usize, usize)> {
+    let elided: HashSet<_> = data
+        .sites
+        .iter()
+        .filter(|site| site.skip.is_none() && !site.used_before && site.used_after.is_none())
+        .filter(|site| matches!(site.strategy, Strategy::Rc | Strategy::Arc))
+        .map(|site| (site.capture, site.id))
+        .collect();
+
+    for mut pending in std::mem::take(&mut data.pending) {
+        pending.nest_block.last_use = pending
+            .nest_block
+            .maybe_last_use
+            .iter()
+            .copied()
+            .filter(|id| elided.contains(&(pending.capture, *id)))
+            .collect();
+
+        if pending.nest_block.last_use.is_empty() {
+            continue;
+        }
+
+        let captures = describe_captures(&data.bindings, &pending.nest_block);
+        let (start, depth) = pending.trace;
+
+        if let Some(trace) = &mut data.trace {
+            for (line, capture) in trace[start..].iter_mut().zip(&captures) {
+                *line = format!("{}  {capture}", "  ".repeat(depth));
+            }
+        }
+
+        if let (Some(report), Some(index)) = (&mut data.report, pending.report) {
+            report[index] = report_closure(
+                pending.kind,
+                pending.span,
+                &data.bindings,
+                &pending.nest_block,
+            );
+        }
+
+        if let Some(index) = pending.explain {
+            data.warnings[index].message = captures.join("; ");
+        }
+
+        if let Some(index) = pending.record {
+            let record = data.manifest_record(pending.kind, pending.span, &pending.nest_block);
+            if let Some(manifest) = &mut data.manifest {
+                manifest[index] = record;
+            }
+        }
+    }
+
+    if let Some(block) = block.filter(|_| data.marked > 0) {
+        LastUses {
+            elided: &elided,
+            remaining: data.marked,
+        }
+        .visit_block_mut(block);
+    }
+
+    elided
+}
+
+/// The marker of the statements cloning a binding the closure `capture` may hold the last use of.
+const LAST_USE_MARKER: &str = "clone_on_capture_last_use";
+
+/// Removes the marked statements of the elided captures and the markers of the others.
+struct LastUses<'a> {
+    elided: &'a HashSet<(usize, usize)>,
+    /// Marked statements not found yet, the rest of the function is skipped once it is zero.
+    remaining: usize,
+}
+
+impl VisitMut for LastUses<'_> {
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        if self.remaining == 0 {
+            return;
+        }
+
+        block.stmts.retain_mut(|stmt| {
+            let attrs = match stmt {
+                Stmt::Local(local) => &mut local.attrs,
+                Stmt::Expr(Expr::Block(expr_block), _) => &mut expr_block.attrs,
+                _ => return true,
+            };
+
+            let marker = match attrs
+                .iter()
+                .position(|attr| attr.path().is_ident(LAST_USE_MARKER))
+            {
+                Some(index) => attrs.remove(index),
+                None => return true,
+            };
+
+            self.remaining = self.remaining.saturating_sub(1);
+
+            marker
+                .parse_args_with(Punctuated::<syn::LitInt, Token![,]>::parse_terminated)
+                .ok()
+                .and_then(|args| {
+                    let mut args = args.iter().map(syn::LitInt::base10_parse::<usize>);
+                    Some((args.next()?.ok()?, args.next()?.ok()?))
+                })
+                .is_none_or(|key| !self.elided.contains(&key))
+        });
+
+        visit_mut::visit_block_mut(self, block);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        if self.remaining > 0 {
+            visit_mut::visit_expr_mut(self, expr);
+        }
+    }
+}
+
+fn parse_generic_statement(data: &mut Data, stmt: &mut Stmt) -> Result<()> {
+    match stmt {
+        Stmt::Local(local) => parse_local_statement(data, local),
+        Stmt::Item(Item::Const(item_const)) => parse_generic_expression(data, &mut item_const.expr),
+        Stmt::Item(Item::Fn(item_fn)) => {
+            data.function.push(item_fn.sig.ident.clone());
+            data.visit_block_mut(&mut item_fn.block);
+            data.function.pop();
+
+            data.visit_result()
+        }
+        Stmt::Item(_) => Ok(()),
+        Stmt::Expr(expr, _) => parse_generic_expression(data, expr),
+        Stmt::Macro(stmt_macro) => parse_macro_tokens(data, &mut stmt_macro.mac),
+    }
+}
+
+fn parse_local_statement(data: &mut Data, local: &mut Local) -> Result<()> {
+    if let Some(local_init) = &mut local.init {
+        parse_generic_expression(data, &mut local_init.expr)?;
+    }
+
+    let shared = shared_pointer(data, local)?;
+    let cell = shared_cell(data, local)?;
+    let mut bindings = extract_pat(data, &local.pat)?;
+
+    if take_attribute(&mut local.attrs, "no_clone") {
+        for binding in bindings.values_mut() {
+            binding.skip = Some(Skip::NoClone);
+        }
+    }
+
+    if let Some(pointer) = shared {
+        wrap_shared(local, &pointer, false)?;
+
+        for binding in bindings.values_mut() {
+            binding.shared = Some(pointer.clone());
+        }
+    }
+
+    if let Some(pointer) = cell {
+        wrap_shared(local, &pointer, true)?;
+
+        for binding in bindings.values_mut() {
+            binding.shared = Some(pointer.clone());
+            binding.cell = true;
+
+            if binding.skip == Some(Skip::Mut) {
+                binding.skip = None;
+            }
+        }
+    }
+
+    data.push_idents(bindings);
+
+    Ok(())
+}
+
+fn parse_generic_expression(data: &mut Data, expr: &mut Expr) -> Result<()> {
+    let rewrite = match expr {
+        Expr::Assign(expr_assign) => parse_assign_expression(data, expr_assign)?,
+        Expr::Async(expr_async) => parse_async_expression(data, expr_async)?,
+        Expr::Binary(expr_binary) => parse_binary_expression(data, expr_binary)?,
+        Expr::Block(expr_block) => parse_block_expression(data, expr_block)?,
+        Expr::Call(expr_call) => parse_call_expression(data, expr_call)?,
+        Expr::Closure(expr_closure) => parse_closure_expression(data, expr_closure)?,
+        Expr::Field(expr_field) => parse_field_expression(data, expr_field)?,
+        Expr::ForLoop(expr_for_loop) => parse_for_loop_expression(data, expr_for_loop)?,
+        Expr::If(expr_if) => parse_if_expression(data, expr_if)?,
+        Expr::Index(expr_index) => parse_index_expression(data, expr_index)?,
+        Expr::Loop(expr_loop) => parse_loop_expression(data, expr_loop)?,
+        Expr::Macro(expr_macro) => parse_macro_expression(data, expr_macro)?,
+        Expr::Match(expr_match) => parse_match_expression(data, expr_match)?,
+        Expr::MethodCall(expr_method_call) => parse_method_call_expression(data, expr_method_call)?,
+        Expr::Path(expr_path) => parse_path_expression(data, expr_path)?,
+        Expr::Reference(expr_reference) => parse_reference_expression(data, expr_reference)?,
+        Expr::Struct(expr_struct) => parse_struct_expression(data, expr_struct)?,
+        Expr::While(expr_while) => parse_while_expression(data, expr_while)?,
+        // The other expressions neither declare nor use bindings themselves, only their
+        // subexpressions are traversed.
+        _ => {
+            visit_mut::visit_expr_mut(data, expr);
+            return data.visit_result();
+        }
+    };
+
+    match rewrite {
+        Some(rewrite) => rewrite.apply(data, expr),
+        None => Ok(()),
+    }
+}
+
+fn parse_struct_expression(
+    data: &mut Data,
+    expr_struct: &mut ExprStruct,
+) -> Result<Option<Rewrite>> {
+    for field in &mut expr_struct.fields {
+        parse_generic_expression(data, &mut field.expr)?;
+    }
+
+    for field in &expr_struct.fields {
+        match &field.member {
+            Member::Named(named) => {
+                data.push_members(HashMap::from([(named.clone(), Binding::default())]));
+            }
+            Member::Unnamed(_) => {}
+        }
+    }
+
+    if let Some(expr) = &mut expr_struct.rest {
+        parse_generic_expression(data, expr)?;
+    }
+
+    Ok(None)
+}
+
+fn parse_reference_expression(
+    data: &mut Data,
+    expr_reference: &mut ExprReference,
+) -> Result<Option<Rewrite>> {
+    if expr_reference.mutability.is_some() {
+        check_shared_mutation(data, &expr_reference.expr)?;
+        data.writing = place_root(&expr_reference.expr).is_some();
+    }
+
+    parse_generic_expression(data, &mut expr_reference.expr)?;
+
+    Ok(None)
+}
+
+fn parse_macro_expression(data: &mut Data, expr_macro: &mut ExprMacro) -> Result<Option<Rewrite>> {
+    if expr_macro
+        .mac
+        .path
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "cc")
+    {
+        return Ok(Some(Rewrite::Unwrap(parse_cc_macro(&expr_macro.mac)?)));
+    }
+
+    parse_macro_tokens(data, &mut expr_macro.mac)?;
+
+    Ok(None)
+}
+
+/// Unwraps the `cc!(...)` marker into the closure or async block it wraps, marked `#[cc]`.
+fn parse_cc_macro(mac: &Macro) -> Result<Expr> {
+    let mut expr: Expr = mac.parse_body()?;
+
+    match &mut expr {
+        Expr::Closure(ExprClosure { attrs, .. }) | Expr::Async(ExprAsync { attrs, .. }) => {
+            attrs.push(parse_quote!(#[cc]));
+        }
+        _ => {
+            return Err(Error::new_spanned(
+                expr,
+                "`cc!` expects a closure or an async block",
+            ))
+        }
+    }
+
+    Ok(expr)
+}
+
+/// Macro arguments are opaque, so every identifier in them counts as a usage. Those that also
+/// appear as paths when the arguments parse as comma separated expressions, like the arguments
+/// of `format!`, are known to be real usages.
+///
+/// Such arguments are traversed like any other expression when the macro is registered in the
+/// `macros` option, or when they use a `#[shared_mut]` binding, whose uses have to be rewritten.
+fn parse_macro_tokens(data: &mut Data, mac: &mut Macro) -> Result<()> {
+    let mut exprs = mac
+        .parse_body_with(Punctuated::<Expr, Token![,]>::parse_terminated)
+        .ok();
+    let mut paths = HashSet::new();
+    let mut usages = HashSet::new();
+
+    // Arguments that parse as expressions only use locals through single segment paths, and
+    // through the tokens of the macros they call. Other arguments are scanned for identifiers.
+    match &exprs {
+        Some(exprs) => {
+            let mut nested = vec![];
+            let mut visitor = PathIdents {
+                paths: &mut paths,
+                macros: &mut nested,
+            };
+
+            for expr in exprs {
+                visitor.visit_expr(expr);
+            }
+
+            usages.extend(paths.iter().cloned());
+
+            for tokens in nested {
+                extract_token_stream(tokens, &mut usages);
+            }
+        }
+        None => extract_token_stream(&mac.tokens, &mut usages),
+    }
+
+    let registered = mac
+        .path
+        .segments
+        .last()
+        .is_some_and(|segment| data.macros.contains(&segment.ident));
+
+    if let (true, Some(exprs)) = (registered, &mut exprs) {
+        for expr in exprs.iter_mut() {
+            parse_generic_expression(data, expr)?;
+        }
+
+        let exprs = exprs.iter();
+        mac.tokens = quote!(#(#exprs),*);
+
+        return Ok(());
+    }
+
+    let usages = usages.into_iter().map(Name::new).collect::<Vec<_>>();
+
+    if let Some(cell) = usages
+        .iter()
+        .find(|usage| data.resolve(usage).is_some_and(|(_, binding)| binding.cell))
+    {
+        let exprs = match &mut exprs {
+            Some(exprs) => exprs,
+            None => {
+                return Err(Error::new(
+                    cell.ident().span(),
+                    format!(
+                        "`{cell}` is `#[shared_mut]`, its uses inside macros are only rewritten \
+                         when the arguments are comma separated expressions"
+                    ),
+                ))
+            }
+        };
+
+        for expr in exprs.iter_mut() {
+            parse_generic_expression(data, expr)?;
+        }
+
+        let exprs = exprs.iter();
+        mac.tokens = quote!(#(#exprs),*);
+
+        return Ok(());
+    }
+
+    for usage in usages {
+        let scanned = !paths.contains(usage.ident());
+
+        if !scanned && data.borrowing(&usage).is_some() {
+            return Err(Error::new(
+                usage.ident().span(),
+                format!(
+                    "`{usage}` stays borrowed by a promoted closure, but uses inside macros \
+                     cannot be rewritten; bind it to a variable outside the macro first"
+                ),
+            ));
+        }
+
+        data.push_usage(usage, scanned)?;
+    }
+
+    Ok(())
+}
+
+/// Collects single segment paths, and the tokens of nested macro calls.
+struct PathIdents<'a, 'ast> {
+    paths: &'a mut HashSet<Ident>,
+    macros: &'a mut Vec<&'ast TokenStream>,
+}
+
+impl<'ast> Visit<'ast> for PathIdents<'_, 'ast> {
+    fn visit_expr_path(&mut self, expr_path: &'ast ExprPath) {
+        if let (None, Some(ident)) = (&expr_path.qself, expr_path.path.get_ident()) {
+            self.paths.insert(ident.clone());
+        }
+    }
+
+    fn visit_macro(&mut self, mac: &'ast Macro) {
+        self.macros.push(&mac.tokens);
+    }
+}
+
+fn parse_index_expression(data: &mut Data, expr_index: &mut ExprIndex) -> Result<Option<Rewrite>> {
+    parse_generic_expression(data, &mut expr_index.index)?;
+    parse_generic_expression(data, &mut expr_index.expr)?;
+
+    Ok(None)
+}
+
+fn parse_for_loop_expression(
+    data: &mut Data,
+    expr_for_loop: &mut ExprForLoop,
+) -> Result<Option<Rewrite>> {
+    parse_generic_expression(data, &mut expr_for_loop.expr)?;
+
+    data.push_loop_block();
+
+    let bindings = extract_pat(data, &expr_for_loop.pat)?;
+    data.push_idents(bindings);
+
+    data.visit_block_mut(&mut expr_for_loop.body);
+    data.visit_result()?;
+
+    data.pop_nested_block()?;
+
+    Ok(None)
+}
+
+fn parse_binary_expression(
+    data: &mut Data,
+    expr_binary: &mut ExprBinary,
+) -> Result<Option<Rewrite>> {
+    let mut cell = false;
+
+    if matches!(
+        expr_binary.op,
+        BinOp::AddAssign(_)
+            | BinOp::SubAssign(_)
+            | BinOp::MulAssign(_)
+            | BinOp::DivAssign(_)
+            | BinOp::RemAssign(_)
+            | BinOp::BitXorAssign(_)
+            | BinOp::BitAndAssign(_)
+            | BinOp::BitOrAssign(_)
+            | BinOp::ShlAssign(_)
+            | BinOp::ShrAssign(_)
+    ) {
+        check_shared_mutation(data, &expr_binary.left)?;
+
+        cell = is_cell_place(data, &expr_binary.left);
+        if cell {
+            data.writing = true;
+        }
+    }
+
+    parse_generic_expression(data, &mut expr_binary.left)?;
+    parse_generic_expression(data, &mut expr_binary.right)?;
+
+    Ok(cell.then_some(Rewrite::CellAssignment))
+}
+
+fn parse_assign_expression(
+    data: &mut Data,
+    expr_assign: &mut ExprAssign,
+) -> Result<Option<Rewrite>> {
+    check_shared_mutation(data, &expr_assign.left)?;
+    let cell = is_cell_place(data, &expr_assign.left);
+
+    data.writing = place_root(&expr_assign.left).is_some();
+    parse_generic_expression(data, &mut expr_assign.left)?;
+    parse_generic_expression(data, &mut expr_assign.right)?;
+
+    Ok(cell.then_some(Rewrite::CellAssignment))
+}
+
+/// Whether the expression uses a `#[shared_mut]` binding.
+fn mentions_cell(data: &Data, expr: &Expr) -> bool {
+    let mut idents = HashSet::new();
+    extract_token_stream(&expr.to_token_stream(), &mut idents);
+
+    idents.iter().any(|ident| {
+        data.resolve(&Name::from(ident))
+            .is_some_and(|(_, binding)| binding.cell)
+    })
+}
+
+/// Evaluates the value of an assignment to a `#[shared_mut]` place, or the arguments of a method
+/// called on one, before borrowing the place, so that borrows of the value, like in
+/// `count = count + 1`, have ended.
+fn cell_assignment(expr: Expr) -> Expr {
+    let value = Ident::new("assigned_value", proc_macro2::Span::mixed_site());
+
+    match expr {
+        Expr::MethodCall(mut expr_method_call) => {
+            let mut values = Vec::new();
+
+            for (i, arg) in expr_method_call.args.iter_mut().enumerate() {
+                let name = Ident::new(&format!("argument_{i}"), proc_macro2::Span::mixed_site());
+                let arg = std::mem::replace(arg, parse_quote!(#name));
+                values.push(quote!(let #name = #arg;));
+            }
+
+            parse_quote!({
+                #(#values)*
+                #expr_method_call
+            })
+        }
+        Expr::Assign(mut expr_assign) => {
+            let right = std::mem::replace(&mut *expr_assign.right, parse_quote!(#value));
+            parse_quote!({
+                let #value = #right;
+                #expr_assign
+            })
+        }
+        Expr::Binary(mut expr_binary) => {
+            let right = std::mem::replace(&mut *expr_binary.right, parse_quote!(#value));
+            parse_quote!({
+                let #value = #right;
+                #expr_binary
+            })
+        }
+        expr => expr,
+    }
+}
+
+/// Evaluates a method call chain on a `#[shared_mut]` binding in its own block, so that the borrow
+/// or lock ends before another use of the binding in the same statement, like in
+/// `items.len() + items.len()`.
+fn cell_call(expr: Expr, arguments_first: bool) -> Expr {
+    let value = Ident::new("called_value", proc_macro2::Span::mixed_site());
+    let expr = match arguments_first {
+        true => cell_assignment(expr),
+        false => expr,
+    };
+
+    parse_quote!({
+        let #value = #expr;
+        #value
+    })
+}
+
+fn parse_loop_expression(data: &mut Data, expr_loop: &mut ExprLoop) -> Result<Option<Rewrite>> {
+    data.push_loop_block();
+
+    data.visit_block_mut(&mut expr_loop.body);
+    data.visit_result()?;
+
+    data.pop_nested_block()?;
+
+    Ok(None)
+}
+
+fn parse_while_expression(data: &mut Data, expr_while: &mut ExprWhile) -> Result<Option<Rewrite>> {
+    data.push_loop_block();
+
+    parse_generic_expression(data, &mut expr_while.cond)?;
+
+    data.visit_block_mut(&mut expr_while.body);
+    data.visit_result()?;
+
+    data.pop_nested_block()?;
+
+    Ok(None)
+}
+
+fn parse_match_expression(data: &mut Data, expr_match: &mut ExprMatch) -> Result<Option<Rewrite>> {
+    parse_generic_expression(data, &mut expr_match.expr)?;
+
+    for arm in &mut expr_match.arms {
+        parse_generic_expression(data, &mut arm.body)?;
+
+        if let Some((_, guard)) = &mut arm.guard {
+            parse_generic_expression(data, guard)?;
+        }
+    }
+
+    Ok(None)
+}
+
+fn parse_field_expression(data: &mut Data, expr_field: &mut ExprField) -> Result<Option<Rewrite>> {
+    if let Some(ident) = data.push_place(expr_field) {
+        return Ok(Some(Rewrite::Replace(parse_quote!(#ident))));
+    }
+
+    parse_generic_expression(data, &mut expr_field.base)?;
+
+    Ok(None)
+}
+
+fn parse_path_expression(data: &mut Data, expr_path: &mut ExprPath) -> Result<Option<Rewrite>> {
+    // Places are traversed root first, so only the root path sees the flag.
+    let writing = std::mem::take(&mut data.writing);
+
+    // Only a single segment without generics, like `config` or `self`, can name a local.
+    // `config::load()`, `Config::config` and `<T>::config` name items even when a local has the
+    // same name as one of their segments.
+    let ident = match (&expr_path.qself, expr_path.path.get_ident()) {
+        (None, Some(ident)) => ident,
+        _ => return Ok(None),
+    };
+    let name = Name::from(ident);
+
+    if let Some(borrowed) = data.push_borrowed(&name) {
+        return Ok(Some(Rewrite::Replace(parse_quote!((*#borrowed)))));
+    }
+
+    if ident == "self" {
+        return Ok(data
+            .push_receiver(expr_path)
+            .map(|ident| Rewrite::Replace(parse_quote!(#ident))));
+    }
+
+    let access = data
+        .resolve(&name)
+        .filter(|(_, binding)| binding.cell)
+        .map(|(_, binding)| cell_access(ident, binding, writing));
+
+    data.push_usage(name, false)?;
+
+    Ok(access.map(Rewrite::Replace))
+}
+
+fn parse_if_expression(data: &mut Data, expr_if: &mut ExprIf) -> Result<Option<Rewrite>> {
+    parse_generic_expression(data, &mut expr_if.cond)?;
+
+    if let Some((_, expr)) = &mut expr_if.else_branch {
+        parse_generic_expression(data, expr)?;
+    }
+
+    data.visit_block_mut(&mut expr_if.then_branch);
+    data.visit_result()?;
+
+    Ok(None)
+}
+
+fn parse_call_expression(data: &mut Data, expr_call: &mut ExprCall) -> Result<Option<Rewrite>> {
+    if let Expr::Path(ExprPath { path, .. }) = &*expr_call.func {
+        if data.targets.iter().any(|target| target.matches_call(path)) {
+            mark_target_arguments(data, expr_call.args.iter_mut());
+        }
+    }
+
+    parse_generic_expression(data, &mut expr_call.func)?;
+
+    for arg in &mut expr_call.args {
+        parse_generic_expression(data, arg)?;
+    }
+
+    Ok(None)
+}
+
+fn parse_closure_expression(
+    data: &mut Data,
+    expr_closure: &mut ExprClosure,
+) -> Result<Option<Rewrite>> {
+    trace!(
+        data,
+        "{} at {}",
+        if expr_closure.capture.is_some() {
+            "move closure"
+        } else {
+            "closure"
+        },
+        location(expr_closure.span())
+    );
+
+    push_captured_block(
+        data,
+        &mut expr_closure.attrs,
+        &mut expr_closure.capture,
+        expr_closure.or1_token.span,
+    );
+
+    for pat in &expr_closure.inputs {
+        let bindings = extract_pat(data, pat)?;
+        data.push_idents(bindings);
+    }
+
+    parse_generic_expression(data, &mut expr_closure.body)?;
+
+    let kind = if expr_closure.asyncness.is_some() {
+        "async closure"
+    } else {
+        "closure"
+    };
+
+    Ok(data
+        .pop_nested_block()?
+        .map(|nest_block| Rewrite::Captured(nest_block, kind)))
+}
+
+fn parse_method_call_expression(
+    data: &mut Data,
+    expr_method_call: &mut ExprMethodCall,
+) -> Result<Option<Rewrite>> {
+    if data
+        .targets
+        .iter()
+        .any(|target| target.matches_method(&expr_method_call.method))
+    {
+        mark_target_arguments(data, expr_method_call.args.iter_mut());
+    }
+
+    let marked = take_attribute(&mut expr_method_call.attrs, "borrow_mut");
+    let chained = std::mem::take(&mut data.chained);
+    let scoped = !chained && is_cell_chain(data, &expr_method_call.receiver);
+
+    // The receiver may be borrowed mutably by the method.
+    let cell = is_cell_place(data, &expr_method_call.receiver);
+    let arguments_first = cell
+        && expr_method_call
+            .args
+            .iter()
+            .any(|arg| mentions_cell(data, arg));
+    data.writing =
+        cell && (marked || MUTATING_METHODS.contains(&&*expr_method_call.method.to_string()));
+    data.chained = matches!(&*expr_method_call.receiver, Expr::MethodCall(_));
+    parse_generic_expression(data, &mut expr_method_call.receiver)?;
+
+    for arg in &mut expr_method_call.args {
+        parse_generic_expression(data, arg)?;
+    }
+
+    Ok(match (scoped, arguments_first) {
+        (true, _) => Some(Rewrite::CellCall(arguments_first)),
+        (false, true) => Some(Rewrite::CellAssignment),
+        (false, false) => None,
+    })
+}
+
+fn parse_block_expression(data: &mut Data, expr_block: &mut ExprBlock) -> Result<Option<Rewrite>> {
+    trace!(data, "block at {}", location(expr_block.span()));
+
+    data.push_nested_block(false);
+
+    data.visit_block_mut(&mut expr_block.block);
+    data.visit_result()?;
+
+    data.pop_nested_block()?;
+
+    Ok(None)
+}
+
+fn parse_async_expression(data: &mut Data, expr_async: &mut ExprAsync) -> Result<Option<Rewrite>> {
+    trace!(
+        data,
+        "{} at {}",
+        if expr_async.capture.is_some() {
+            "async move block"
+        } else {
+            "async block"
+        },
+        location(expr_async.span())
+    );
+
+    push_captured_block(
+        data,
+        &mut expr_async.attrs,
+        &mut expr_async.capture,
+        expr_async.async_token.span,
+    );
+
+    data.visit_block_mut(&mut expr_async.block);
+    data.visit_result()?;
+
+    Ok(data
+        .pop_nested_block()?
+        .map(|nest_block| Rewrite::Captured(nest_block, "async")))
+}
+
+/// Finishes a closure or async block: reports what it captures and generates the clones.
+fn captured_expression(
+    data: &mut Data,
+    mut nest_block: NestBlock,
+    kind: &'static str,
+    expr: Expr,
+) -> Result<Expr> {
+    let span = start_span(&expr);
+
+    if nest_block.capture {
+        data.captures += 1;
+        nest_block.maybe_last_use = lint_captures(data, &nest_block);
+    }
+    let captures = describe_captures(&data.bindings, &nest_block);
+    let trace = (data.trace.as_ref().map_or(0, Vec::len), data.nested.len());
+
+    for capture in &captures {
+        trace!(data, "  {capture}");
+    }
+
+    if nest_block.promoted && !nest_block.borrowed.is_empty() {
+        let mut borrowed = nest_block
+            .borrowed
+            .iter()
+            .filter_map(|(ident, borrow)| {
+                borrow
+                    .binding
+                    .skip
+                    .as_ref()
+                    .map(|skip| format!("`{ident}` ({})", skip.reason()))
+            })
+            .collect::<Vec<_>>();
+        borrowed.sort();
+
+        data.warnings.push(Warning {
+            name: "promote",
+            span,
+            message: format!(
+                "the promoted closure keeps {} borrowed, it is not `'static`",
+                borrowed.join(", ")
+            ),
+        });
+    }
+
+    let report = data.report.as_mut().map(|report| {
+        report.push(report_closure(kind, span, &data.bindings, &nest_block));
+        report.len() - 1
+    });
+
+    let explain = data.explain.then(|| {
+        data.warnings.push(Warning {
+            name: "explain",
+            span,
+            message: captures.join("; "),
+        });
+        data.warnings.len() - 1
+    });
+
+    let record = match nest_block.capture && !data.analysis_only {
+        true => data.record(kind, span, &nest_block),
+        false => None,
+    };
+
+    if !nest_block.maybe_last_use.is_empty() {
+        data.pending.push(PendingClosure {
+            kind,
+            span,
+            nest_block: nest_block.clone(),
+            capture: data.captures,
+            trace,
+            report,
+            explain,
+            record,
+        });
+    }
+
+    if nest_block.capture && !data.analysis_only {
+        let (expr, context) = propagated_expression(data, nest_block.send, expr);
+        return cloned_idents_expression(data, nest_block, context, expr);
+    }
+
+    Ok(expr)
+}
+
+/// Lints the captures of a closure and records them as sites, returns the ids of the reference
+/// counted bindings it may hold the last use of.
+fn lint_captures(data: &mut Data, nest_block: &NestBlock) -> HashSet<usize> {
+    let mut maybe_last_use = HashSet::new();
+    let mut usage: Vec<_> = nest_block
+        .used(&data.bindings)
+        .into_iter()
+        .map(|(ident, binding, strategy)| (ident, binding.id, binding.skip.clone(), strategy))
+        .collect();
+    usage.sort_by_key(|(ident, ..)| ident.to_string());
+
+    for (ident, id, skip, strategy) in usage {
+        if skip.is_some() {
+            if matches!(skip, Some(Skip::Prefix(_))) {
+                data.push_site(CaptureSite {
+                    id,
+                    capture: data.captures,
+                    strategy,
+                    ident: ident.ident().clone(),
+                    skip,
+                    used_before: false,
+                    used_after: None,
+                    scanned: false,
+                });
+            }
+            continue;
+        }
+
+        if !strategy.clones() {
+            continue;
+        }
+
+        let scanned = nest_block.scanned.contains(ident);
+        let used_before = data.used_directly.contains(&id);
+        let last_use = data.last_uses
+            && !used_before
+            && matches!(strategy, Strategy::Rc | Strategy::Arc)
+            && !data.in_loop(ident)
+            && !data.in_closure(ident);
+
+        // Reported once the function has been traversed, unless the capture is a last use.
+        if scanned && !last_use {
+            data.warn(
+                Lint::MacroOnlyCapture,
+                ident.span(),
+                format!("`{ident}` is only referenced inside macro tokens, it may be cloned without being used"),
+            );
+        }
+
+        if data.in_loop(ident) {
+            data.warn(
+                Lint::CloneInLoop,
+                ident.span(),
+                format!("`{ident}` is cloned on every iteration of the enclosing loop"),
+            );
+        } else if !data.in_closure(ident) {
+            // Inside another closure the capture runs again with each of its calls, it is
+            // neither a last use nor a redundant clone.
+            data.push_site(CaptureSite {
+                id,
+                capture: data.captures,
+                strategy,
+                ident: ident.ident().clone(),
+                skip: None,
+                used_before,
+                used_after: None,
+                scanned: scanned && last_use,
+            });
+
+            if last_use {
+                maybe_last_use.insert(id);
+            }
+        }
+    }
+
+    maybe_last_use
+}
+
+/// The report entry of a closure or async block, with the reasons `describe_captures` gives.
+fn report_closure(
+    kind: &'static str,
+    span: proc_macro2::Span,
+    bindings: &HashMap<usize, Binding>,
+    nest_block: &NestBlock,
+) -> ClosureReport {
+    let rewritten = nest_block.capture && !nest_block.opted_out;
+
+    let usage = nest_block
+        .used(bindings)
+        .into_iter()
+        .map(|(ident, binding, strategy)| {
+            let (strategy, reason) = match &binding.skip {
+                _ if !nest_block.capture => (
+                    "borrow".to_string(),
+                    "not `move`, nothing cloned".to_string(),
+                ),
+                _ if nest_block.opted_out => (
+                    "move".to_string(),
+                    "not marked `#[cc]` or passed to a configured target".to_string(),
+                ),
+                Some(skip) => ("move".to_string(), skip.reason()),
+                None => (strategy.name(), strategy.describe(ident.ident())),
+            };
+
+            CaptureEntry {
+                name: ident.to_string(),
+                span: ident.span(),
+                strategy,
+                reason,
+            }
+        });
+
+    let places = nest_block.places.iter().map(|(key, place)| CaptureEntry {
+        name: key.clone(),
+        span: place.expr.span(),
+        strategy: "clone".to_string(),
+        reason: match key.as_str() {
+            "self" => "clones `self`".to_string(),
+            key => format!("clones field path `{key}`"),
+        },
+    });
+
+    let borrowed = nest_block
+        .borrowed
+        .iter()
+        .map(|(ident, borrow)| CaptureEntry {
+            name: ident.to_string(),
+            span: ident.span(),
+            strategy: "borrow".to_string(),
+            reason: borrow
+                .binding
+                .skip
+                .as_ref()
+                .map_or_else(|| "borrowed".to_string(), Skip::reason),
+        });
+
+    let mut captures: Vec<CaptureEntry> = usage.chain(places).chain(borrowed).collect();
+    captures.sort_by(|a, b| a.name.cmp(&b.name));
+
+    ClosureReport {
+        kind,
+        span,
+        rewritten,
+        captures,
+    }
+}
+
+fn describe_captures(bindings: &HashMap<usize, Binding>, nest_block: &NestBlock) -> Vec<String> {
+    if nest_block.usage.is_empty() && nest_block.places.is_empty() && nest_block.borrowed.is_empty()
+    {
+        return vec!["captures nothing".to_string()];
+    }
+
+    if nest_block.opted_out {
+        return vec![format!(
+            "moves {} as written (not marked `#[cc]` or passed to a configured target)",
+            Data::string_idents(nest_block.usage.keys())
+        )];
+    }
+
+    if !nest_block.capture {
+        return vec![format!(
+            "borrows {} (not `move`, nothing cloned)",
+            Data::string_idents(nest_block.usage.keys())
+        )];
+    }
+
+    let mut result = vec![];
+    let (cloned, other): (Vec<_>, Vec<_>) =
+        nest_block
+            .used(bindings)
+            .into_iter()
+            .partition(|(_, binding, strategy)| {
+                binding.skip.is_none() && *strategy == Strategy::Capture
+            });
+
+    if !cloned.is_empty() {
+        result.push(format!(
+            "clones {}",
+            Data::string_idents(cloned.iter().map(|(ident, _, _)| *ident))
+        ));
+    }
+
+    if !nest_block.places.is_empty() {
+        let mut places = nest_block
+            .places
+            .keys()
+            .map(|key| format!("`{key}`"))
+            .collect::<Vec<_>>();
+        places.sort();
+        result.push(format!("clones field paths {}", places.join(", ")));
+    }
+
+    let mut borrowed = nest_block
+        .borrowed
+        .iter()
+        .filter_map(|(ident, borrow)| {
+            borrow
+                .binding
+                .skip
+                .as_ref()
+                .map(|skip| format!("`{ident}` stays borrowed: {}", skip.reason()))
+        })
+        .collect::<Vec<_>>();
+    borrowed.sort();
+    result.extend(borrowed);
+
+    let mut other: Vec<String> = other
+        .into_iter()
+        .map(|(ident, binding, strategy)| match &binding.skip {
+            Some(skip) => format!("`{ident}` skipped: {}", skip.reason()),
+            None => strategy.describe(ident.ident()),
+        })
+        .collect();
+    other.sort();
+    result.extend(other);
+
+    result
+}
+
+/// Marks the closure and async block arguments of a call to a configured target with `#[cc]`.
+fn mark_target_arguments<'a>(data: &mut Data, args: impl Iterator<Item = &'a mut Expr>) {
+    for arg in args {
+        if let Expr::Closure(ExprClosure { attrs, .. }) | Expr::Async(ExprAsync { attrs, .. }) = arg
+        {
+            trace!(data, "argument of a configured target");
+            attrs.push(parse_quote!(#[cc]));
+        }
+    }
+}
+
+/// Pushes the block of a closure or async block, handling its `#[cc]`, `#[promote]` and `#[send]`
+/// markers.
+fn push_captured_block(
+    data: &mut Data,
+    attrs: &mut Vec<Attribute>,
+    capture: &mut Option<Token![move]>,
+    span: proc_macro2::Span,
+) {
+    let opted_in = take_attribute(attrs, "cc");
+    let promoted = promote(data, attrs, capture, span, opted_in);
+    let rewrite = capture.is_some() && (opted_in || promoted || !data.opt_in);
+
+    if capture.is_some() && !rewrite {
+        trace!(data, "left as written (not marked `#[cc]`)");
+    }
+
+    data.push_nested_block(rewrite);
+    data.mark_closure();
+    data.mark_promoted(promoted);
+    data.mark_opted_out(capture.is_some() && !rewrite);
+    data.mark_send(take_attribute(attrs, "send"));
+}
+
+/// Makes a closure or async block `move` when the `promote` argument or a `#[promote]` marker asks
+/// for it, returns whether it was promoted. In `opt_in` mode the argument only applies to blocks
+/// marked `#[cc]`.
+fn promote(
+    data: &mut Data,
+    attrs: &mut Vec<Attribute>,
+    capture: &mut Option<Token![move]>,
+    span: proc_macro2::Span,
+    opted_in: bool,
+) -> bool {
+    let marked = take_attribute(attrs, "promote");
+    let promote = data.promote && (opted_in || !data.opt_in);
+
+    if capture.is_some() || !(marked || promote) {
+        return false;
+    }
+
+    trace!(data, "promoted to move");
+    *capture = Some(Token![move](span));
+
+    true
+}
+
+/// The pointer a declaration is wrapped in, from a `#[shared]` or `#[shared(local)]` marker or the
+/// `shared` arguments.
+fn shared_pointer(data: &mut Data, local: &mut Local) -> Result<Option<Strategy>> {
+    if let Some(index) = local
+        .attrs
+        .iter()
+        .position(|attr| attr.path().is_ident("shared"))
+    {
+        let attr = local.attrs.remove(index);
+
+        return match &attr.meta {
+            Meta::Path(_) => Ok(Some(Strategy::Arc)),
+            Meta::List(list) if list.tokens.to_string() == "local" => Ok(Some(Strategy::Rc)),
+            _ => Err(Error::new_spanned(
+                attr,
+                "expected `#[shared]` or `#[shared(local)]`",
+            )),
+        };
+    }
+
+    let ident = match &local.pat {
+        Pat::Ident(pat_ident) => &pat_ident.ident,
+        Pat::Type(pat_type) => match &*pat_type.pat {
+            Pat::Ident(pat_ident) => &pat_ident.ident,
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+
+    match data.shared.iter().find(|(name, _)| name == ident) {
+        Some((name, pointer)) => {
+            data.shared_declared.insert(name.clone());
+            Ok(Some(pointer.clone()))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The pointer a `#[shared_mut]` or `#[shared_mut(send)]` declaration is wrapped in, with a
+/// `Mutex` for `Arc` and a `RefCell` for `Rc`.
+fn shared_cell(data: &Data, local: &mut Local) -> Result<Option<Strategy>> {
+    let index = match local
+        .attrs
+        .iter()
+        .position(|attr| attr.path().is_ident("shared_mut"))
+    {
+        Some(index) => index,
+        None => return Ok(None),
+    };
+
+    let attr = local.attrs.remove(index);
+
+    match &attr.meta {
+        Meta::Path(_) if data.send => Ok(Some(Strategy::Arc)),
+        Meta::Path(_) => Ok(Some(Strategy::Rc)),
+        Meta::List(list) if list.tokens.to_string() == "send" => Ok(Some(Strategy::Arc)),
+        _ => Err(Error::new_spanned(
+            attr,
+            "expected `#[shared_mut]` or `#[shared_mut(send)]`",
+        )),
+    }
+}
+
+/// Wraps the initializer of a shared declaration, and its type annotation, in `Arc` or `Rc`, and
+/// in a `Mutex` or `RefCell` for `cell`.
+fn wrap_shared(local: &mut Local, pointer: &Strategy, cell: bool) -> Result<()> {
+    let span = local.pat.span();
+    let (pointer, inner): (Path, Option<Path>) = match (pointer, cell) {
+        (Strategy::Rc, false) => (parse_quote_spanned!(span=> ::std::rc::Rc), None),
+        (Strategy::Rc, true) => (
+            parse_quote_spanned!(span=> ::std::rc::Rc),
+            Some(parse_quote_spanned!(span=> ::std::cell::RefCell)),
+        ),
+        (_, false) => (parse_quote_spanned!(span=> ::std::sync::Arc), None),
+        (_, true) => (
+            parse_quote_spanned!(span=> ::std::sync::Arc),
+            Some(parse_quote_spanned!(span=> ::std::sync::Mutex)),
+        ),
+    };
+
+    let pat_ident = match &mut local.pat {
+        Pat::Ident(pat_ident) => pat_ident,
+        Pat::Type(pat_type) => {
+            let ty = &pat_type.ty;
+            pat_type.ty = match &inner {
+                Some(inner) => parse_quote_spanned!(span=> #pointer<#inner<#ty>>),
+                None => parse_quote_spanned!(span=> #pointer<#ty>),
+            };
+
+            match &mut *pat_type.pat {
+                Pat::Ident(pat_ident) => pat_ident,
+                pat => {
+                    return Err(Error::new_spanned(
+                        pat,
+                        "shared declarations must bind a single identifier",
+                    ))
+                }
+            }
+        }
+        pat => {
+            return Err(Error::new_spanned(
+                pat,
+                "shared declarations must bind a single identifier",
+            ))
+        }
+    };
+
+    if cell {
+        // The value is mutated through the cell, the binding itself never is.
+        pat_ident.mutability = None;
+    } else if let Some(mutability) = pat_ident.mutability {
+        return Err(Error::new_spanned(
+            mutability,
+            format!(
+                "`{}` is shared through a reference counted pointer and cannot be `mut`",
+                pat_ident.ident
+            ),
+        ));
+    }
+
+    match &mut local.init {
+        Some(local_init) if local_init.diverge.is_none() => {
+            let expr = &local_init.expr;
+            local_init.expr = match &inner {
+                Some(inner) => parse_quote_spanned!(span=> #pointer::new(#inner::new(#expr))),
+                None => parse_quote_spanned!(span=> #pointer::new(#expr)),
+            };
+            Ok(())
+        }
+        _ => Err(Error::new_spanned(
+            &local.pat,
+            "shared declarations need an initializer and cannot use `let ... else`",
+        )),
+    }
+}
+
+/// Borrows or locks the cell of a `#[shared_mut]` binding for a use of it.
+fn cell_access(ident: &Ident, binding: &Binding, writing: bool) -> Expr {
+    let span = ident.span();
+
+    match (&binding.shared, writing) {
+        (Some(Strategy::Arc), _) => parse_quote_spanned!(span=> (*#ident.lock().unwrap())),
+        (_, true) => parse_quote_spanned!(span=> (*#ident.borrow_mut())),
+        (_, false) => parse_quote_spanned!(span=> (*#ident.borrow())),
+    }
+}
+
+/// The binding a place expression like `a.b[0]` is rooted at.
+fn place_root(mut place: &Expr) -> Option<&Ident> {
+    loop {
+        match place {
+            Expr::Field(expr_field) => place = &expr_field.base,
+            Expr::Index(expr_index) => place = &expr_index.expr,
+            Expr::Paren(expr_paren) => place = &expr_paren.expr,
+            Expr::Unary(ExprUnary {
+                op: UnOp::Deref(_),
+                expr,
+                ..
+            }) => place = expr,
+            Expr::Path(expr_path) => return expr_path.path.get_ident(),
+            _ => return None,
+        }
+    }
+}
+
+/// Whether the type annotation `ty` names a value rather than a pointer to one, fields of an
+/// unannotated binding may be reached through an `Rc` or `Arc`.
+fn is_plain_value(ty: Option<&Type>) -> bool {
+    match ty {
+        Some(Type::Paren(paren)) => is_plain_value(Some(&paren.elem)),
+        Some(Type::Group(group)) => is_plain_value(Some(&group.elem)),
+        Some(Type::Tuple(_) | Type::Array(_)) => true,
+        Some(Type::Path(TypePath { qself: None, path })) => path
+            .segments
+            .last()
+            .is_some_and(|segment| !matches!(segment.ident.to_string().as_str(), "Rc" | "Arc")),
+        _ => false,
+    }
+}
+
+/// Whether the method call chain, like `log.iter().count()`, is called on a place rooted at a
+/// `#[shared_mut]` binding.
+fn is_cell_chain(data: &Data, mut receiver: &Expr) -> bool {
+    while let Expr::MethodCall(expr_method_call) = receiver {
+        receiver = &expr_method_call.receiver;
+    }
+
+    is_cell_place(data, receiver)
+}
+
+/// Whether the place is rooted at a `#[shared_mut]` binding.
+fn is_cell_place(data: &Data, place: &Expr) -> bool {
+    place_root(place)
+        .and_then(|ident| data.resolve(&Name::from(ident)))
+        .is_some_and(|(_, binding)| binding.cell)
+}
+
+/// Reports assignments and mutable borrows of places rooted at a shared binding. Method call
+/// receivers are not checked, whether the method takes `&mut self` is not known here.
+fn check_shared_mutation(data: &Data, place: &Expr) -> Result<()> {
+    let ident = match place_root(place) {
+        Some(ident) => ident,
+        None => return Ok(()),
+    };
+
+    match data.resolve(&Name::from(ident)) {
+        Some((_, binding)) if binding.shared.is_some() && !binding.cell => Err(Error::new(
+            ident.span(),
+            format!("`{ident}` is shared through `Arc` or `Rc` and cannot be used mutably"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Removes the inert marker attribute `#[name]`, returns whether it was present.
+fn take_attribute(attrs: &mut Vec<Attribute>, name: &str) -> bool {
+    let len = attrs.len();
+    attrs.retain(|attr| !attr.path().is_ident(name));
+    attrs.len() != len
+}
+
+/// The span of the first token of a closure or async block. `Spanned::span` would walk all of
+/// its tokens to join the first span with the last, which only nightly compilers support.
+fn start_span(expr: &Expr) -> proc_macro2::Span {
+    match expr {
+        Expr::Closure(expr_closure) => expr_closure
+            .attrs
+            .first()
+            .map(|attr| attr.pound_token.span)
+            .or_else(|| Some(expr_closure.lifetimes.as_ref()?.for_token.span))
+            .or_else(|| Some(expr_closure.constness?.span))
+            .or_else(|| Some(expr_closure.movability?.span))
+            .or_else(|| Some(expr_closure.asyncness?.span))
+            .or_else(|| Some(expr_closure.capture?.span))
+            .unwrap_or(expr_closure.or1_token.span),
+        Expr::Async(expr_async) => expr_async
+            .attrs
+            .first()
+            .map(|attr| attr.pound_token.span)
+            .unwrap_or(expr_async.async_token.span),
+        expr => expr.span(),
+    }
+}
+
+/// Splits `a.b.0` into its base binding and members, `None` unless the base is a plain identifier.
+fn field_path(mut expr_field: &ExprField) -> Option<(Ident, Vec<String>)> {
+    let mut members = vec![];
+
+    loop {
+        members.push(match &expr_field.member {
+            Member::Named(ident) => ident.unraw().to_string(),
+            Member::Unnamed(index) => index.index.to_string(),
+        });
+
+        match &*expr_field.base {
+            Expr::Field(base) => expr_field = base,
+            Expr::Path(expr_path) if expr_path.qself.is_none() => {
+                let root = expr_path.path.get_ident()?.clone();
+                members.reverse();
+                return Some((root, members));
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn extract_pat(data: &Data, pat: &Pat) -> Result<HashMap<Ident, Binding>> {
+    let mut result = HashMap::default();
+
+    match pat {
+        Pat::Ident(pat_ident) => {
+            let skip = if !data.skip_prefix.is_empty()
+                && pat_ident.ident.to_string().starts_with(&data.skip_prefix)
+            {
+                Some(Skip::Prefix(data.skip_prefix.clone()))
+            } else if data.skip.contains(&pat_ident.ident) {
+                Some(Skip::Listed)
+            } else if data
+                .only
+                .as_ref()
+                .is_some_and(|only| !only.contains(&pat_ident.ident))
+            {
+                Some(Skip::NotOnly)
+            } else if pat_ident.mutability.is_some() && data.move_mut {
+                Some(Skip::Mut)
+            } else {
+                None
+            };
+
+            if let Some((_, subpat)) = &pat_ident.subpat {
+                result.extend(extract_pat(data, subpat)?);
+            }
+
+            result.insert(
+                pat_ident.ident.clone(),
+                Binding {
+                    skip,
+                    ..Default::default()
+                },
+            );
+        }
+        Pat::Struct(pat_struct) => {
+            for field in &pat_struct.fields {
+                result.extend(extract_pat(data, &field.pat)?);
+            }
+        }
+        Pat::Tuple(pat_tuple) => {
+            for field in &pat_tuple.elems {
+                result.extend(extract_pat(data, field)?);
+            }
+        }
+        Pat::Type(pat_type) => result.extend(extract_pat_type(data, pat_type)?),
+        Pat::TupleStruct(pat_tuple_struct) => {
+            for pat in &pat_tuple_struct.elems {
+                result.extend(extract_pat(data, pat)?);
+            }
+        }
+        _ => {}
+    }
+
+    Ok(result)
+}
+
+/// The bindings of `pat: ty`, a single identifier keeps the type annotation.
+fn extract_pat_type(data: &Data, pat_type: &PatType) -> Result<HashMap<Ident, Binding>> {
+    let mut result = extract_pat(data, &pat_type.pat)?;
+
+    if let Pat::Ident(pat_ident) = &*pat_type.pat {
+        if let Some(binding) = result.get_mut(&pat_ident.ident) {
+            binding.ty = Some((*pat_type.ty).clone());
+        }
+    }
+
+    Ok(result)
+}
+
+/// Adds the identifiers of `stream` and of the groups nested in it to `result`.
+fn extract_token_stream(stream: &TokenStream, result: &mut HashSet<Ident>) {
+    for tree in stream.clone() {
+        match tree {
+            TokenTree::Group(group) => extract_token_stream(&group.stream(), result),
+            TokenTree::Ident(value) => {
+                result.insert(value);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Methods of the standard collections, `String` and `Option` that take `&mut self`, their
+/// receivers borrow a `#[shared_mut]` binding mutably. Other methods need `#[borrow_mut]`.
+const MUTATING_METHODS: [&str; 32] = [
+    "append",
+    "as_mut",
+    "clear",
+    "dedup",
+    "drain",
+    "entry",
+    "extend",
+    "first_mut",
+    "get_mut",
+    "get_or_insert_with",
+    "insert",
+    "iter_mut",
+    "last_mut",
+    "pop",
+    "pop_back",
+    "pop_front",
+    "push",
+    "push_back",
+    "push_front",
+    "push_str",
+    "remove",
+    "replace",
+    "resize",
+    "retain",
+    "reverse",
+    "sort",
+    "sort_by",
+    "sort_by_key",
+    "sort_unstable",
+    "swap_remove",
+    "take",
+    "truncate",
+];
+
+/// Clippy lints the generated captures trigger, they mirror the user's own code.
+const GENERATED_ALLOW: [&str; 3] = ["shadow_reuse", "shadow_same", "shadow_unrelated"];
+
+/// Enters the context of the `propagate` hook around the body of a closure or every poll of an
+/// async block, returns the expression and the prologue statements that take the context.
+fn propagated_expression(data: &mut Data, send: bool, expr: Expr) -> (Expr, Vec<Stmt>) {
+    let hook = match &data.propagate {
+        Some(hook) => hook.clone(),
+        None => return (expr, vec![]),
+    };
+
+    let span = start_span(&expr);
+    let context = Ident::new("propagated_context", proc_macro2::Span::mixed_site());
+    let guard = Ident::new("_propagated_guard", proc_macro2::Span::mixed_site());
+
+    let expr = match expr {
+        Expr::Closure(mut expr_closure) if expr_closure.asyncness.is_none() => {
+            let body = &expr_closure.body;
+            expr_closure.body = parse_quote_spanned! {span=>
+                {
+                    let #guard = ::clone_on_capture::runtime::enter(&#context);
+                    #body
+                }
+            };
+            Expr::Closure(expr_closure)
+        }
+        Expr::Async(expr_async) => parse_quote_spanned! {span=>
+            ::clone_on_capture::runtime::Propagated::new(#context, #expr_async)
+        },
+        expr => {
+            trace!(data, "  context not propagated into async closure");
+            return (expr, vec![]);
+        }
+    };
+
+    let mut stmts = vec![parse_quote_spanned! {span=>
+        let #context = #hook();
+    }];
+
+    if send {
+        stmts.push(parse_quote_spanned! {span=>
+            ::clone_on_capture::runtime::assert_send(&#context);
+        });
+    }
+
+    (expr, stmts)
+}
+
+/// Reports a clone of `value` to `runtime::trace::record`, with the `trace_clones` option.
+fn traced_clone(data: &Data, binding: &str, value: &Expr, span: proc_macro2::Span) -> Option<Stmt> {
+    if !data.trace_clones {
+        return None;
+    }
+
+    let file = span.file();
+    let function = manifest::function_path(&file, &data.function);
+    let line = u32::try_from(span.start().line).unwrap_or(u32::MAX);
+
+    Some(parse_quote_spanned! {span=>
+        {
+            static CLONE_ON_CAPTURE_SITE: ::clone_on_capture::runtime::trace::Site =
+                ::clone_on_capture::runtime::trace::Site {
+                    function: #function,
+                    file: #file,
+                    line: #line,
+                    binding: #binding,
+                };
+            ::clone_on_capture::runtime::trace::record(
+                &CLONE_ON_CAPTURE_SITE,
+                ::core::mem::size_of_val(&#value),
+            );
+        }
+    })
+}
+
+fn cloned_idents_expression(
+    data: &mut Data,
+    nest_block: NestBlock,
+    context: Vec<Stmt>,
+    expr: Expr,
+) -> Result<Expr> {
+    let mut stmts: Vec<Stmt> = vec![];
+    let line = u32::try_from(expr.span().start().line).unwrap_or(u32::MAX);
+
+    let mut usage = nest_block.used(&data.bindings);
+    usage.sort_by_key(|(name, ..)| name.to_string());
+
+    for (name, binding, strategy) in usage {
+        let ident = name.ident();
+        let span = ident.span();
+        let allow = GENERATED_ALLOW.map(|lint| Ident::new(lint, span));
+        let marked = stmts.len();
+
+        if binding.skip.is_none() && strategy.clones() {
+            stmts.extend(traced_clone(
+                data,
+                &ident.to_string(),
+                &parse_quote!(#ident),
+                span,
+            ));
+        }
+
+        match (&binding.skip, &strategy) {
+            (Some(_), _) | (None, Strategy::Ref | Strategy::Move | Strategy::LastUse) => {}
+            (None, Strategy::Capture) if data.cheap_only => {
+                stmts.push(parse_quote_spanned! {span=>
+                    #[allow(#(clippy::#allow),*)]
+                    let #ident = ::clone_on_capture::runtime::cheap_clone_capture(&#ident);
+                })
+            }
+            (None, Strategy::Capture) => stmts.push(parse_quote_spanned! {span=>
+                #[allow(#(clippy::#allow),*)]
+                let #ident = {
+                    // Named like the binding for the error, a braced struct is only a type.
+                    #[allow(non_camel_case_types, dead_code)]
+                    struct #ident {}
+                    ::clone_on_capture::runtime::CheckCapture::<#ident, #line, _>::check(&#ident);
+                    ::clone_on_capture::runtime::capture(&#ident)
+                };
+            }),
+            (None, Strategy::Rc) => stmts.push(parse_quote_spanned! {span=>
+                #[allow(#(clippy::#allow),*)]
+                let #ident = ::std::rc::Rc::clone(&#ident);
+            }),
+            (None, Strategy::Arc) => stmts.push(parse_quote_spanned! {span=>
+                #[allow(#(clippy::#allow),*)]
+                let #ident = ::std::sync::Arc::clone(&#ident);
+            }),
+            (None, Strategy::Method(method)) => stmts.push(parse_quote_spanned! {span=>
+                #[allow(#(clippy::#allow),*)]
+                let #ident = #ident.#method();
+            }),
+            (None, Strategy::Weak) => stmts.push(parse_quote_spanned! {span=>
+                #[allow(#(clippy::#allow),*)]
+                let #ident = ::clone_on_capture::runtime::downgrade(&#ident);
+            }),
+        }
+
+        if nest_block.maybe_last_use.contains(&binding.id) {
+            let marker = Ident::new(LAST_USE_MARKER, span);
+            let key = [data.captures, binding.id].map(proc_macro2::Literal::usize_unsuffixed);
+            let marker: Attribute = parse_quote!(#[#marker(#(#key),*)]);
+
+            for stmt in &mut stmts[marked..] {
+                match stmt {
+                    Stmt::Local(local) => local.attrs.push(marker.clone()),
+                    Stmt::Expr(Expr::Block(expr_block), _) => expr_block.attrs.push(marker.clone()),
+                    _ => continue,
+                }
+                data.marked += 1;
+            }
+        }
+
+        if nest_block.send {
+            stmts.push(parse_quote_spanned! {span=>
+                {
+                    #[allow(non_camel_case_types, dead_code)]
+                    struct #ident {}
+                    ::clone_on_capture::runtime::CheckSend::<#ident, #line, _>::check(&#ident);
+                }
+            });
+        }
+    }
+
+    let mut borrowed: Vec<(Name, Borrow)> = nest_block.borrowed.into_iter().collect();
+    borrowed.sort_by_key(|(ident, _)| ident.to_string());
+
+    for (original, borrow) in borrowed {
+        let span = original.span();
+        let ident = borrow.ident;
+
+        // An enclosing promoted closure that uses the same binding already holds a reference.
+        let source: Expr = if data
+            .nested
+            .iter()
+            .any(|nest| nest.borrowed.contains_key(&original))
+        {
+            parse_quote!(*#ident)
+        } else {
+            parse_quote!(#original)
+        };
+
+        if borrow.binding.skip == Some(Skip::Mut) {
+            stmts.push(parse_quote_spanned! {span=>
+                let #ident = &mut #source;
+            });
+        } else {
+            stmts.push(parse_quote_spanned! {span=>
+                let #ident = &#source;
+            });
+        }
+    }
+
+    let mut places: Vec<(String, Place)> = nest_block.places.into_iter().collect();
+    places.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    for (key, place) in places {
+        let span = place.expr.span();
+        let ident = place.ident;
+
+        // An enclosing closure that uses the same place already holds a clone of it.
+        let source: Expr = if data
+            .nested
+            .iter()
+            .any(|nest| nest.places.contains_key(&key))
+        {
+            parse_quote!(#ident)
+        } else {
+            place.expr
+        };
+
+        stmts.extend(traced_clone(data, &key, &source, span));
+
+        if data.cheap_only {
+            stmts.push(parse_quote_spanned! {span=>
+                let #ident = ::clone_on_capture::runtime::cheap_clone_capture(&#source);
+            });
+        } else {
+            stmts.push(parse_quote_spanned! {span=>
+                let #ident = ::clone_on_capture::runtime::capture(&#source);
+            });
+        }
+
+        if nest_block.send {
+            stmts.push(parse_quote_spanned! {span=>
+                ::clone_on_capture::runtime::assert_send(&#ident);
+            });
+        }
+    }
+
+    stmts.extend(context);
+    stmts.push(Stmt::Expr(expr, None));
+
+    Ok(Expr::Block(ExprBlock {
+        attrs: vec![],
+        label: None,
+        block: Block {
+            brace_token: Default::default(),
+            stmts,
+        },
+    }))
+}
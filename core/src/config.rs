@@ -0,0 +1,182 @@
+use proc_macro2::Span;
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::{parse_quote, Error, Ident, Result, Stmt};
+use toml::{Table, Value};
+
+use crate::diagnostics::Lint;
+use crate::options::Options;
+use crate::strategy::Strategy;
+
+/// Crate-wide defaults, read from the crate root.
+pub const CONFIG_FILE: &str = "clone-on-capture.toml";
+
+/// Table of `Cargo.toml` read when there is no [`CONFIG_FILE`].
+const METADATA_TABLE: &str = "clone-on-capture";
+
+/// The options of the configuration of the crate being compiled, the defaults when it has none.
+///
+/// [`CONFIG_FILE`] takes precedence over `[package.metadata.clone-on-capture]` in `Cargo.toml`.
+pub fn load() -> Result<Options> {
+    let dir = match std::env::var_os("CARGO_MANIFEST_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => return Ok(Options::default()),
+    };
+
+    let file = dir.join(CONFIG_FILE);
+    if file.is_file() {
+        return parse(&read(&file)?)
+            .map(|options| Options {
+                config: Some(file.clone()),
+                ..options
+            })
+            .map_err(|err| config_error(&file, err));
+    }
+
+    let manifest = dir.join("Cargo.toml");
+    if !manifest.is_file() {
+        return Ok(Options::default());
+    }
+
+    let table = read(&manifest)?;
+    match table
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get(METADATA_TABLE))
+    {
+        Some(Value::Table(table)) => parse(table)
+            .map(|options| Options {
+                config: Some(manifest.clone()),
+                ..options
+            })
+            .map_err(|err| config_error(&manifest, err)),
+        Some(_) => Err(config_error(
+            &manifest,
+            format!("`package.metadata.{METADATA_TABLE}` must be a table"),
+        )),
+        None => Ok(Options::default()),
+    }
+}
+
+/// A statement including the configuration file in the output. Cargo does not track the files
+/// proc macros read, this makes changes to it expand the crate again.
+pub fn dependency(path: &Path) -> Stmt {
+    let path = path.to_string_lossy();
+    let path = path.as_ref();
+
+    parse_quote!(
+        const _: &[u8] = include_bytes!(#path);
+    )
+}
+
+fn read(path: &Path) -> Result<Table> {
+    fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|content| content.parse::<Table>().map_err(|err| err.to_string()))
+        .map_err(|err| config_error(path, err))
+}
+
+fn config_error(path: &Path, message: String) -> Error {
+    Error::new(
+        Span::call_site(),
+        format!(
+            "invalid clone_on_capture configuration in {}: {}",
+            path.display(),
+            message.trim_end()
+        ),
+    )
+}
+
+fn parse(table: &Table) -> std::result::Result<Options, String> {
+    let mut options = Options::default();
+
+    for (key, value) in table {
+        match key.as_str() {
+            "skip_prefix" => options.skip_prefix = Some(string(key, value)?.to_string()),
+            "strategy" => options.strategy = strategy(key, value)?,
+            "mut_bindings" => {
+                options.clone_mut = match string(key, value)? {
+                    "move" => false,
+                    "clone" => true,
+                    other => {
+                        return Err(format!(
+                            "unknown `mut_bindings` policy `{other}`, expected `move` or `clone`"
+                        ))
+                    }
+                }
+            }
+            "send" => options.send = boolean(key, value)?,
+            "cheap_only" => options.cheap_only = boolean(key, value)?,
+            "promote" => options.promote = boolean(key, value)?,
+            "opt_in" => options.opt_in = boolean(key, value)?,
+            "macros" => {
+                options.macros = array(key, value)?
+                    .iter()
+                    .map(|name| {
+                        let name = string("macros", name)?;
+                        syn::parse_str::<Ident>(name.trim_end_matches('!'))
+                            .map_err(|_| format!("`{name}` in `macros` is not a macro name"))
+                    })
+                    .collect::<std::result::Result<_, _>>()?
+            }
+            "lints" => {
+                for (name, level) in self::table(key, value)? {
+                    let lint =
+                        Lint::from_name(name).ok_or_else(|| format!("unknown lint `{name}`"))?;
+
+                    match string(name, level)? {
+                        "warn" => {
+                            options.lints.insert(lint);
+                        }
+                        "allow" => {
+                            options.lints.remove(&lint);
+                        }
+                        other => {
+                            return Err(format!(
+                            "unknown level `{other}` of lint `{name}`, expected `warn` or `allow`"
+                        ))
+                        }
+                    }
+                }
+            }
+            "types" => {
+                for (ty, value) in self::table(key, value)? {
+                    let path = syn::parse_str(ty)
+                        .map_err(|_| format!("`{ty}` in `types` is not a type path"))?;
+                    options.types.push((path, strategy(ty, value)?));
+                }
+            }
+            _ => return Err(format!("unknown key `{key}`")),
+        }
+    }
+
+    Ok(options)
+}
+
+fn string<'a>(key: &str, value: &'a Value) -> std::result::Result<&'a str, String> {
+    value
+        .as_str()
+        .ok_or_else(|| format!("`{key}` must be a string"))
+}
+
+fn boolean(key: &str, value: &Value) -> std::result::Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or_else(|| format!("`{key}` must be a boolean"))
+}
+
+fn array<'a>(key: &str, value: &'a Value) -> std::result::Result<&'a Vec<Value>, String> {
+    value
+        .as_array()
+        .ok_or_else(|| format!("`{key}` must be an array"))
+}
+
+fn table<'a>(key: &str, value: &'a Value) -> std::result::Result<&'a Table, String> {
+    value
+        .as_table()
+        .ok_or_else(|| format!("`{key}` must be a table"))
+}
+
+fn strategy(key: &str, value: &Value) -> std::result::Result<Strategy, String> {
+    Strategy::parse(string(key, value)?, Span::call_site()).map_err(|err| err.to_string())
+}
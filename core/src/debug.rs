@@ -0,0 +1,50 @@
+use proc_macro2::Span;
+use std::fs;
+use std::path::Path;
+use syn::{Error, File, Item, ItemFn, Result};
+
+/// Writes the trace and the pretty-printed code before and after the expansion of `before`.
+pub fn write_dump(dir: &Path, before: &ItemFn, after: &ItemFn, trace: &[String]) -> Result<()> {
+    let start = before.sig.ident.span().start();
+    let file = before.sig.ident.span().file();
+    let file_name = format!(
+        "{}-{}-{}.rs",
+        file.replace(['/', '\\', ':', '.'], "_"),
+        start.line,
+        before.sig.ident
+    );
+
+    let mut dump = format!(
+        "// clone_on_capture expansion of `{}` ({}:{})\n\n// ---- trace ----\n",
+        before.sig.ident, file, start.line
+    );
+    for line in trace {
+        dump.push_str(&format!("// {line}\n"));
+    }
+    dump.push_str("\n// ---- before ----\n");
+    dump.push_str(&pretty(before));
+    dump.push_str("\n// ---- after ----\n");
+    dump.push_str(&pretty(after));
+
+    fs::create_dir_all(dir)
+        .and_then(|_| fs::write(dir.join(&file_name), dump))
+        .map_err(|err| {
+            Error::new(
+                Span::call_site(),
+                format!("failed to write debug dump to {}: {err}", dir.display()),
+            )
+        })
+}
+
+fn pretty(item_fn: &ItemFn) -> String {
+    prettyplease::unparse(&File {
+        shebang: None,
+        attrs: vec![],
+        items: vec![Item::Fn(item_fn.clone())],
+    })
+}
+
+pub fn location(span: Span) -> String {
+    let start = span.start();
+    format!("{}:{}", start.line, start.column + 1)
+}
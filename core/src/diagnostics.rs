@@ -0,0 +1,75 @@
+use proc_macro2::Span;
+use syn::{parse_quote_spanned, Ident, Stmt};
+
+/// Warnings raised by the macro's own analysis, enabled with the `lint` argument.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// A binding is cloned into a closure but never used afterwards.
+    RedundantClone,
+    /// A `dc_` binding is moved into a closure and used afterwards.
+    MovedDcBinding,
+    /// A binding declared outside a loop is cloned on every iteration.
+    CloneInLoop,
+    /// A capture was only found by scanning macro tokens.
+    MacroOnlyCapture,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 4] = [
+        Lint::RedundantClone,
+        Lint::MovedDcBinding,
+        Lint::CloneInLoop,
+        Lint::MacroOnlyCapture,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::RedundantClone => "redundant_clone",
+            Lint::MovedDcBinding => "moved_dc_binding",
+            Lint::CloneInLoop => "clone_in_loop",
+            Lint::MacroOnlyCapture => "macro_only_capture",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Self::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Warning {
+    /// Identifies the warning in the compiler output, a lint name or `explain`.
+    pub name: &'static str,
+    pub span: Span,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn lint(lint: Lint, span: Span, message: String) -> Self {
+        Warning {
+            name: lint.name(),
+            span,
+            message: format!(
+                "{message} (allow with `#[clone_on_capture(allow({}))]`)",
+                lint.name()
+            ),
+        }
+    }
+
+    /// A statement that makes the compiler report the warning on its span, proc macros cannot
+    /// emit warnings on stable so this uses a deprecated item.
+    pub fn to_stmt(&self) -> Stmt {
+        let span = self.span;
+        let ident = Ident::new(&format!("clone_on_capture_{}", self.name), span);
+        let message = &self.message;
+
+        parse_quote_spanned! {span=>
+            {
+                #[deprecated(note = #message)]
+                #[allow(non_camel_case_types)]
+                struct #ident;
+                let _ = #ident;
+            }
+        }
+    }
+}
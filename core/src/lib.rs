@@ -0,0 +1,22 @@
+//! The analysis and rewriting behind the `clone_on_capture` attribute.
+//!
+//! The proc macro crate only parses its arguments and calls [`transform`], or [`capture`] for
+//! `capture!`. Macros that generate
+//! functions can call it on their own output, and [`analyze`] reports what each closure
+//! captures without rewriting anything.
+
+mod capture_input;
+mod clone_on_capture;
+mod config;
+mod debug;
+mod diagnostics;
+mod manifest;
+mod name;
+mod options;
+mod report;
+mod strategy;
+
+pub use crate::capture_input::{CaptureBinding, CaptureInput};
+pub use crate::clone_on_capture::{analyze, capture, transform};
+pub use crate::options::Options;
+pub use crate::report::{CaptureEntry, CaptureReport, ClosureReport};
@@ -0,0 +1,186 @@
+use proc_macro2::Span;
+use quote::ToTokens;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use syn::{Error, Ident, Result, Type};
+
+use crate::clone_on_capture::Binding;
+use crate::name::Name;
+use crate::strategy::Strategy;
+
+/// A transformed closure or async block.
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub function: String,
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub kind: &'static str,
+    pub captures: Vec<Capture>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Capture {
+    pub name: String,
+    pub strategy: String,
+    pub ty: Option<String>,
+}
+
+impl Record {
+    pub fn new(
+        function: &[Ident],
+        kind: &'static str,
+        span: Span,
+        usage: Vec<(&Name, &Binding, Strategy)>,
+        extra: impl IntoIterator<Item = (String, &'static str)>,
+    ) -> Self {
+        let start = span.start();
+        let file = span.file();
+
+        let mut captures: Vec<Capture> = usage
+            .into_iter()
+            .map(|(ident, binding, strategy)| Capture {
+                name: ident.to_string(),
+                strategy: match binding.skip {
+                    Some(_) => "move".to_string(),
+                    None => strategy.name(),
+                },
+                ty: binding.ty.as_ref().map(type_string),
+            })
+            .chain(extra.into_iter().map(|(name, strategy)| Capture {
+                name,
+                strategy: strategy.to_string(),
+                ty: None,
+            }))
+            .collect();
+        captures.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Record {
+            function: function_path(&file, function),
+            file,
+            line: start.line,
+            column: start.column + 1,
+            kind,
+            captures,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let captures = self
+            .captures
+            .iter()
+            .map(|capture| {
+                format!(
+                    "{{\"name\":{},\"strategy\":{},\"type\":{}}}",
+                    json_string(&capture.name),
+                    json_string(&capture.strategy),
+                    capture
+                        .ty
+                        .as_deref()
+                        .map(json_string)
+                        .unwrap_or_else(|| "null".to_string())
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        format!(
+            "{{\"function\":{},\"file\":{},\"line\":{},\"column\":{},\"kind\":{},\"captures\":[{}]}}",
+            json_string(&self.function),
+            json_string(&self.file),
+            self.line,
+            self.column,
+            json_string(self.kind),
+            captures
+        )
+    }
+}
+
+/// Appends one JSON line per record to the manifest at `path`.
+pub fn append(path: &Path, records: &[Record]) -> Result<()> {
+    if records.is_empty() {
+        return Ok(());
+    }
+
+    let lines: String = records
+        .iter()
+        .map(|record| record.to_json() + "\n")
+        .collect();
+
+    path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| OpenOptions::new().create(true).append(true).open(path))
+        .and_then(|mut file| file.write_all(lines.as_bytes()))
+        .map_err(|err| {
+            Error::new(
+                Span::call_site(),
+                format!("failed to write capture manifest {}: {err}", path.display()),
+            )
+        })
+}
+
+/// Best effort path of the function, macros cannot see the module tree so it is derived from
+/// the crate name and the source file.
+pub fn function_path(file: &str, function: &[Ident]) -> String {
+    let mut segments = vec![std::env::var("CARGO_CRATE_NAME").unwrap_or_default()];
+
+    let file = Path::new(file).with_extension("");
+    let mut components: Vec<String> = file
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+
+    match components.first().map(String::as_str) {
+        Some("src") if components.get(1).map(String::as_str) == Some("bin") => components.clear(),
+        Some("src") => {
+            components.remove(0);
+        }
+        Some("tests" | "examples" | "benches") => components.clear(),
+        _ => {}
+    }
+    if matches!(
+        components.last().map(String::as_str),
+        Some("lib" | "main" | "mod")
+    ) {
+        components.pop();
+    }
+
+    segments.extend(components);
+    segments.extend(function.iter().map(Ident::to_string));
+    segments.retain(|segment| !segment.is_empty());
+    segments.join("::")
+}
+
+fn type_string(ty: &Type) -> String {
+    let item = format!("type T = {};", ty.to_token_stream());
+    syn::parse_file(&item)
+        .map(|file| prettyplease::unparse(&file))
+        .ok()
+        .and_then(|item| {
+            item.trim()
+                .strip_prefix("type T = ")
+                .and_then(|ty| ty.strip_suffix(';'))
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| ty.to_token_stream().to_string())
+}
+
+fn json_string(value: &str) -> String {
+    let mut result = String::from("\"");
+
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+
+    result.push('"');
+    result
+}
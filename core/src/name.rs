@@ -0,0 +1,92 @@
+use proc_macro2::{Span, TokenStream};
+use quote::ToTokens;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use syn::Ident;
+
+/// A binding name that compares the hygiene of its span along with the string, so that an `x`
+/// written by a `macro_rules!` expansion and the caller's `x` are different bindings, like they
+/// are for the compiler.
+///
+/// `Ident` equality only compares the string. The original `Ident` is kept, with its span, for
+/// code generation. The context is computed once, when the name is created, so lookups should
+/// create a name per identifier and pass it on.
+#[derive(Clone, Debug)]
+pub struct Name {
+    ident: Ident,
+    context: Option<u32>,
+}
+
+impl Name {
+    pub fn new(ident: Ident) -> Self {
+        let context = hygiene(ident.span());
+        Name { ident, context }
+    }
+
+    pub fn ident(&self) -> &Ident {
+        &self.ident
+    }
+}
+
+/// The syntax context of a span, best-effort.
+///
+/// There is no API for it, but the compiler prints spans as `#3 bytes(10..11)`, with the context
+/// first. The format is not stable: when it has no `#N` prefix, like outside of the compiler or if
+/// a release changes it, this is `None` and names only compare their strings, like `Ident` does.
+fn hygiene(span: Span) -> Option<u32> {
+    format!("{span:?}")
+        .strip_prefix('#')?
+        .split(' ')
+        .next()?
+        .parse()
+        .ok()
+}
+
+impl From<&Ident> for Name {
+    fn from(ident: &Ident) -> Self {
+        Name::new(ident.clone())
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Self) -> bool {
+        self.ident == other.ident && self.context == other.context
+    }
+}
+
+impl Eq for Name {}
+
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.ident.hash(state);
+        self.context.hash(state);
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.ident.fmt(f)
+    }
+}
+
+impl ToTokens for Name {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self.ident.to_tokens(tokens);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hygiene_fallback() {
+        // `proc_macro2` spans outside of the compiler have no `#N` prefix.
+        let span = Span::call_site();
+        assert_eq!(hygiene(span), None);
+
+        let name = Name::new(Ident::new("x", span));
+        assert_eq!(name, Name::new(Ident::new("x", Span::mixed_site())));
+        assert_ne!(name, Name::new(Ident::new("y", span)));
+    }
+}
@@ -0,0 +1,330 @@
+use quote::ToTokens;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{Error, Expr, ExprLit, Ident, Lit, Meta, MetaList, Path, Result, Token};
+
+use crate::config;
+use crate::diagnostics::Lint;
+use crate::strategy::{path_matches, Strategy};
+
+/// Environment variable that overrides the `debug` argument of every annotated item.
+///
+/// `0` turns dumps off, `1` turns them on with the default location and any other
+/// value is used as the dump directory.
+pub const DEBUG_ENV: &str = "CLONE_ON_CAPTURE_DEBUG";
+
+/// Environment variable that overrides the `manifest` argument, with the same values as
+/// [`DEBUG_ENV`].
+pub const MANIFEST_ENV: &str = "CLONE_ON_CAPTURE_MANIFEST";
+
+/// Arguments of the attribute, the default is the attribute without arguments and without a
+/// crate configuration.
+#[derive(Clone, Default, Debug)]
+pub struct Options {
+    /// Directory expansion dumps are written to, `None` when dumps are disabled.
+    pub dump: Option<PathBuf>,
+    /// File capture records are appended to, `None` when the manifest is disabled.
+    pub manifest: Option<PathBuf>,
+    /// Report the captures of every closure as a warning spanned to the closure.
+    pub explain: bool,
+    /// Captures of every closure must be `Send + 'static`.
+    pub send: bool,
+    /// Captures may only be cloned if they implement `CheapClone`.
+    pub cheap_only: bool,
+    /// Turn every non-`move` closure and async block into a `move` one that clones its captures.
+    pub promote: bool,
+    /// Only rewrite closures and async blocks marked `#[cc]` or wrapped in `cc!`.
+    pub opt_in: bool,
+    /// Calls whose closure and async block arguments are rewritten, all others are left as
+    /// written like in `opt_in` mode. Empty unless the `targets` argument is given.
+    pub targets: Vec<Target>,
+    /// Hook whose result is entered around every rewritten closure and async block.
+    pub propagate: Option<Path>,
+    /// Declarations wrapped in `Arc` (`Strategy::Arc`) or `Rc` (`Strategy::Rc`).
+    pub shared: Vec<(Ident, Strategy)>,
+    /// Lints raised as warnings, empty unless the `lint` argument is given.
+    pub lints: HashSet<Lint>,
+    /// Capture strategies of the `types(...)` table, keyed by type path.
+    pub types: Vec<(Path, Strategy)>,
+    /// Bindings starting with it are moved instead of cloned, `None` for `dc_`.
+    pub skip_prefix: Option<String>,
+    /// Strategy of bindings whose type annotation, if any, does not choose one.
+    pub strategy: Strategy,
+    /// `mut` bindings are cloned like the others instead of being moved.
+    pub clone_mut: bool,
+    /// Macros whose arguments are traversed as comma separated expressions instead of being
+    /// scanned for identifiers.
+    pub macros: Vec<Ident>,
+    /// Bindings moved instead of cloned, from the `skip(...)` argument.
+    pub skip: Vec<Ident>,
+    /// The only bindings that are cloned, from the `only(...)` argument.
+    pub only: Option<Vec<Ident>>,
+    /// The configuration file the options were read from, included in the output so that cargo
+    /// tracks it.
+    pub config: Option<PathBuf>,
+    /// Report every clone to `runtime::trace::record`. Not an argument, the proc-macro crate sets
+    /// it from its `trace` feature.
+    pub trace_clones: bool,
+}
+
+/// A call of the `targets(...)` argument.
+#[derive(Clone, Debug)]
+pub enum Target {
+    /// `thread::spawn`, matches calls of paths ending with the same segments.
+    Function(Path),
+    /// `.connect_clicked`, matches method calls by name.
+    Method(Ident),
+}
+
+impl Target {
+    pub fn matches_call(&self, path: &Path) -> bool {
+        match self {
+            Target::Function(target) => path_matches(path, target),
+            Target::Method(_) => false,
+        }
+    }
+
+    pub fn matches_method(&self, method: &Ident) -> bool {
+        match self {
+            Target::Function(_) => false,
+            Target::Method(target) => target == method,
+        }
+    }
+}
+
+impl Parse for Target {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.peek(Token![.]) {
+            input.parse::<Token![.]>()?;
+            Ok(Target::Method(input.parse()?))
+        } else {
+            Ok(Target::Function(input.parse()?))
+        }
+    }
+}
+
+impl Options {
+    /// Parses the arguments of the attribute, like `send, types(Handle = "arc")`, on top of the
+    /// crate configuration, and applies the environment overrides of `debug` and `manifest`.
+    pub fn parse(args: Punctuated<Meta, Token![,]>) -> Result<Self> {
+        let mut options = config::load()?;
+        let mut lint = false;
+        let mut allowed = HashSet::new();
+
+        for arg in args {
+            match arg {
+                Meta::Path(path) => match name(&path).as_str() {
+                    "debug" => options.dump = Some(default_dump_dir()),
+                    "manifest" => options.manifest = Some(default_manifest_path()),
+                    "explain" => options.explain = true,
+                    "lint" => lint = true,
+                    "send" => options.send = true,
+                    "cheap_only" => options.cheap_only = true,
+                    "promote" => options.promote = true,
+                    "opt_in" => options.opt_in = true,
+                    _ => return Err(unknown_argument(&path)),
+                },
+                Meta::List(list) => match name(&list.path).as_str() {
+                    "debug" => options.dump = Some(output_path(list, "dump", default_dump_dir())?),
+                    "manifest" => {
+                        options.manifest = Some(output_path(list, "path", default_manifest_path())?)
+                    }
+                    "allow" => allowed.extend(lint_names(list)?),
+                    "types" => options.types.extend(type_strategies(list)?),
+                    "shared" => options.shared.extend(shared_names(list, Strategy::Arc)?),
+                    "shared_local" => options.shared.extend(shared_names(list, Strategy::Rc)?),
+                    "targets" => options.targets.extend(
+                        list.parse_args_with(Punctuated::<Target, Token![,]>::parse_terminated)?,
+                    ),
+                    "macros" => options.macros.extend(
+                        list.parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated)?,
+                    ),
+                    "skip" => options.skip.extend(
+                        list.parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated)?,
+                    ),
+                    "only" => options.only.get_or_insert_with(Vec::new).extend(
+                        list.parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated)?,
+                    ),
+                    _ => return Err(unknown_argument(&list.path)),
+                },
+                Meta::NameValue(value) => match name(&value.path).as_str() {
+                    "propagate" => match value.value {
+                        Expr::Path(hook) => options.propagate = Some(hook.path),
+                        _ => {
+                            return Err(Error::new_spanned(
+                                value.value,
+                                "expected the path of a function, like `propagate = tracing::Span::current`",
+                            ))
+                        }
+                    },
+                    "skip_prefix" => options.skip_prefix = Some(string_value(&value.value)?),
+                    "strategy" => {
+                        options.strategy =
+                            Strategy::parse(&string_value(&value.value)?, value.value.span())?
+                    }
+                    "mut_bindings" => {
+                        options.clone_mut = match string_value(&value.value)?.as_str() {
+                            "move" => false,
+                            "clone" => true,
+                            _ => {
+                                return Err(Error::new_spanned(
+                                    value.value,
+                                    "expected `mut_bindings = \"move\"` or `mut_bindings = \"clone\"`",
+                                ))
+                            }
+                        }
+                    }
+                    _ => return Err(unknown_argument(&value.path)),
+                },
+            }
+        }
+
+        // `lint` turns on every lint, on top of the ones the configuration turns on.
+        if lint {
+            options.lints = Lint::ALL.into_iter().collect();
+        }
+        options.lints.retain(|lint| !allowed.contains(lint));
+
+        options.dump = env_override(DEBUG_ENV, options.dump, default_dump_dir);
+        options.manifest = env_override(MANIFEST_ENV, options.manifest, default_manifest_path);
+
+        Ok(options)
+    }
+}
+
+/// Cargo's target directory for the crate being compiled.
+///
+/// Proc macros are not told where it is, so this honours `CARGO_TARGET_DIR` and otherwise
+/// looks for the closest `target` directory above the manifest.
+pub fn target_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("CARGO_TARGET_DIR") {
+        return PathBuf::from(dir);
+    }
+
+    let manifest_dir = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap_or_default());
+
+    manifest_dir
+        .ancestors()
+        .map(|dir| dir.join("target"))
+        .find(|dir| dir.is_dir())
+        .unwrap_or_else(|| manifest_dir.join("target"))
+}
+
+fn default_dump_dir() -> PathBuf {
+    target_dir().join("clone-on-capture").join("debug")
+}
+
+fn default_manifest_path() -> PathBuf {
+    target_dir().join("clone-on-capture").join("manifest.jsonl")
+}
+
+/// Relative paths are resolved against the target directory.
+fn resolve_path(path: String) -> PathBuf {
+    let path = PathBuf::from(path);
+
+    if path.is_absolute() {
+        path
+    } else {
+        target_dir().join(path)
+    }
+}
+
+/// Parses `name(key = "path")`.
+fn output_path(list: MetaList, key: &str, default: PathBuf) -> Result<PathBuf> {
+    let nested = list.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+    let mut path = default;
+
+    for meta in nested {
+        match meta {
+            Meta::NameValue(value) if name(&value.path) == key => {
+                path = resolve_path(string_value(&value.value)?);
+            }
+            _ => {
+                return Err(Error::new_spanned(
+                    meta,
+                    format!("expected `{key} = \"path\"`"),
+                ))
+            }
+        }
+    }
+
+    Ok(path)
+}
+
+/// Parses `allow(lint, ...)`.
+fn lint_names(list: MetaList) -> Result<Vec<Lint>> {
+    let nested = list.parse_args_with(Punctuated::<Path, Token![,]>::parse_terminated)?;
+
+    nested
+        .iter()
+        .map(|path| {
+            Lint::from_name(&name(path)).ok_or_else(|| {
+                Error::new_spanned(
+                    path,
+                    format!("unknown clone_on_capture lint `{}`", name(path)),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Parses `shared(name, ...)`.
+fn shared_names(list: MetaList, pointer: Strategy) -> Result<Vec<(Ident, Strategy)>> {
+    let nested = list.parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated)?;
+
+    Ok(nested
+        .into_iter()
+        .map(|ident| (ident, pointer.clone()))
+        .collect())
+}
+
+/// Parses `types(Path = "strategy", ...)`.
+fn type_strategies(list: MetaList) -> Result<Vec<(Path, Strategy)>> {
+    let nested = list.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+
+    nested
+        .into_iter()
+        .map(|meta| match meta {
+            Meta::NameValue(value) => {
+                let strategy = Strategy::parse(&string_value(&value.value)?, value.value.span())?;
+                Ok((value.path, strategy))
+            }
+            _ => Err(Error::new_spanned(meta, "expected `Type = \"strategy\"`")),
+        })
+        .collect()
+}
+
+fn env_override(var: &str, path: Option<PathBuf>, default: fn() -> PathBuf) -> Option<PathBuf> {
+    match std::env::var(var) {
+        Ok(value) => match value.as_str() {
+            "" | "0" => None,
+            "1" => path.or_else(|| Some(default())),
+            path => Some(resolve_path(path.to_string())),
+        },
+        Err(_) => path,
+    }
+}
+
+fn name(path: &Path) -> String {
+    path.into_token_stream().to_string().to_lowercase()
+}
+
+fn unknown_argument(path: &Path) -> Error {
+    Error::new_spanned(
+        path,
+        format!("unknown clone_on_capture argument `{}`", name(path)),
+    )
+}
+
+fn string_value(expr: &Expr) -> Result<String> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(value),
+            ..
+        }) => Ok(value.value()),
+        _ => Err(Error::new_spanned(expr, "expected a string literal")),
+    }
+}
@@ -0,0 +1,48 @@
+use proc_macro2::Span;
+
+/// What the closures and async blocks of a function capture, in traversal order.
+#[derive(Clone, Debug, Default)]
+pub struct CaptureReport {
+    pub closures: Vec<ClosureReport>,
+}
+
+/// A closure or async block and its captures.
+#[derive(Clone, Debug)]
+pub struct ClosureReport {
+    /// `closure`, `async` or `async closure`.
+    pub kind: &'static str,
+    pub span: Span,
+    /// The captures are cloned, false for closures without `move` and ones left as written in
+    /// `opt_in` mode.
+    pub rewritten: bool,
+    /// Sorted by name.
+    pub captures: Vec<CaptureEntry>,
+}
+
+/// A binding or field path used by a closure.
+#[derive(Clone, Debug)]
+pub struct CaptureEntry {
+    /// The binding, or the dotted field path like `self.config`.
+    pub name: String,
+    /// The first use inside the closure.
+    pub span: Span,
+    /// `clone`, `rc`, `arc`, `ref`, `move`, `borrow` or the name of the method called.
+    pub strategy: String,
+    /// Why the capture is made this way, like the `explain` warnings.
+    pub reason: String,
+}
+
+impl CaptureReport {
+    /// The closure or async block that starts on `line`.
+    pub fn closure_at(&self, line: usize) -> Option<&ClosureReport> {
+        self.closures
+            .iter()
+            .find(|closure| closure.span.start().line == line)
+    }
+}
+
+impl ClosureReport {
+    pub fn capture(&self, name: &str) -> Option<&CaptureEntry> {
+        self.captures.iter().find(|capture| capture.name == name)
+    }
+}
@@ -0,0 +1,130 @@
+use proc_macro2::Span;
+use syn::{Error, Ident, Path, Result, Type, TypePath};
+
+/// How a captured binding is moved into a closure.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub enum Strategy {
+    /// `Capture::capture(&a)`, the default.
+    #[default]
+    Capture,
+    /// `Rc::clone(&a)`.
+    Rc,
+    /// `Arc::clone(&a)`.
+    Arc,
+    /// `a.method()`.
+    Method(Ident),
+    /// `Rc::downgrade(&a)` or `Arc::downgrade(&a)`, through `runtime::downgrade`.
+    Weak,
+    /// Copied, for shared references.
+    Ref,
+    /// Moved without a call, for `Copy` primitives and mutable references.
+    Move,
+    /// Moved because the closure holds the last use of a reference counted binding.
+    LastUse,
+}
+
+const PRIMITIVES: [&str; 16] = [
+    "bool", "char", "f32", "f64", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32",
+    "u64", "u128", "usize",
+];
+
+impl Strategy {
+    /// Parses a strategy of the `types(Path = "strategy")` table.
+    pub fn parse(value: &str, span: Span) -> Result<Self> {
+        match value {
+            "clone" | "capture" => Ok(Strategy::Capture),
+            "rc" => Ok(Strategy::Rc),
+            "arc" => Ok(Strategy::Arc),
+            "weak" => Ok(Strategy::Weak),
+            "ref" => Ok(Strategy::Ref),
+            "move" | "copy" => Ok(Strategy::Move),
+            method => syn::parse_str::<Ident>(method)
+                .map(|method| Strategy::Method(Ident::new(&method.to_string(), span)))
+                .map_err(|_| {
+                    Error::new(
+                        span,
+                        format!(
+                            "unknown capture strategy `{method}`, expected `clone`, `rc`, `arc`, \
+                             `weak`, `ref`, `move` or a method name"
+                        ),
+                    )
+                }),
+        }
+    }
+
+    /// The strategy for a binding with the type annotation `ty`, entries of `table` take
+    /// precedence over the built in ones, `default` is used when neither chooses one.
+    pub fn for_type(ty: Option<&Type>, table: &[(Path, Strategy)], default: &Strategy) -> Self {
+        let ty = match ty {
+            Some(ty) => ty,
+            None => return default.clone(),
+        };
+
+        match ty {
+            Type::Reference(reference) if reference.mutability.is_some() => Strategy::Move,
+            Type::Reference(_) => Strategy::Ref,
+            Type::Paren(paren) => Self::for_type(Some(&paren.elem), table, default),
+            Type::Group(group) => Self::for_type(Some(&group.elem), table, default),
+            Type::Path(TypePath { qself: None, path }) => {
+                if let Some((_, strategy)) = table.iter().find(|(key, _)| path_matches(path, key)) {
+                    return strategy.clone();
+                }
+
+                let last = match path.segments.last() {
+                    Some(segment) => segment.ident.to_string(),
+                    None => return default.clone(),
+                };
+
+                match last.as_str() {
+                    "Rc" => Strategy::Rc,
+                    "Arc" => Strategy::Arc,
+                    name if path.segments.len() == 1 && PRIMITIVES.contains(&name) => {
+                        Strategy::Move
+                    }
+                    _ => default.clone(),
+                }
+            }
+            _ => default.clone(),
+        }
+    }
+
+    /// Whether the strategy calls a function to produce the captured value.
+    pub fn clones(&self) -> bool {
+        !matches!(self, Strategy::Ref | Strategy::Move | Strategy::LastUse)
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Strategy::Capture => "clone".to_string(),
+            Strategy::Rc => "rc".to_string(),
+            Strategy::Arc => "arc".to_string(),
+            Strategy::Method(method) => method.to_string(),
+            Strategy::Weak => "weak".to_string(),
+            Strategy::Ref => "ref".to_string(),
+            Strategy::Move | Strategy::LastUse => "move".to_string(),
+        }
+    }
+
+    pub fn describe(&self, ident: &Ident) -> String {
+        match self {
+            Strategy::Capture => format!("clones `{ident}`"),
+            Strategy::Rc => format!("`{ident}` captured with `Rc::clone`"),
+            Strategy::Arc => format!("`{ident}` captured with `Arc::clone`"),
+            Strategy::Method(method) => format!("`{ident}` captured with `.{method}()`"),
+            Strategy::Weak => format!("`{ident}` captured as a weak reference"),
+            Strategy::Ref => format!("`{ident}` copied (shared reference)"),
+            Strategy::Move => format!("`{ident}` moved (Copy or mutable reference type)"),
+            Strategy::LastUse => {
+                format!("`{ident}` moved (last use of a reference counted binding)")
+            }
+        }
+    }
+}
+
+/// Whether the type path ends with the segments of `key`, ignoring generic arguments.
+pub fn path_matches(path: &Path, key: &Path) -> bool {
+    let path: Vec<&Ident> = path.segments.iter().map(|segment| &segment.ident).collect();
+    let key: Vec<&Ident> = key.segments.iter().map(|segment| &segment.ident).collect();
+
+    path.len() >= key.len() && path[path.len() - key.len()..] == key[..]
+}
@@ -0,0 +1,130 @@
+use clone_on_capture_core::{capture, transform, CaptureInput, Options};
+use quote::{quote, ToTokens};
+use std::fs;
+use std::path::PathBuf;
+use syn::{parse_quote, ItemFn};
+
+/// A crate directory with the given files, used as `CARGO_MANIFEST_DIR`.
+fn crate_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "clone-on-capture-config-{}-{name}",
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+
+    for (file, content) in files {
+        fs::write(dir.join(file), content).unwrap();
+    }
+
+    std::env::set_var("CARGO_MANIFEST_DIR", &dir);
+    dir
+}
+
+fn expand(args: proc_macro2::TokenStream) -> syn::Result<String> {
+    let input: ItemFn = parse_quote! {
+        fn foo() {
+            let keep_a = "a".to_string();
+            let dc_b = "b".to_string();
+            let mut c = 0;
+            let _closure = move || (keep_a.len(), dc_b.len(), c += 1, log!(keep_a.len()));
+        }
+    };
+
+    let options = Options::parse(parse_quote!(#args))?;
+    Ok(transform(input, options)?
+        .block
+        .stmts
+        .last()
+        .into_token_stream()
+        .to_string())
+}
+
+// A single test, the configuration is read from the process environment.
+#[test]
+fn configuration() {
+    let dir = crate_dir(
+        "file",
+        &[
+            (
+                "clone-on-capture.toml",
+                r#"
+skip_prefix = "keep_"
+strategy = "duplicate"
+mut_bindings = "clone"
+macros = ["log!"]
+
+[lints]
+redundant_clone = "warn"
+"#,
+            ),
+            (
+                "Cargo.toml",
+                "[package.metadata.clone-on-capture]\nskip_prefix = \"ignored_\"\n",
+            ),
+        ],
+    );
+
+    let expansion = expand(quote!()).unwrap();
+    assert!(!expansion.contains("let keep_a"));
+    assert!(expansion.contains("let dc_b = dc_b . duplicate ()"));
+    assert!(expansion.contains("let c = c . duplicate ()"));
+    // `log!` is traversed, so its use of `keep_a` is not a scanned usage.
+    assert!(expansion.contains("log ! (keep_a . len ())"));
+
+    // The file is a dependency of the output.
+    let input: ItemFn = parse_quote!(
+        fn foo() {}
+    );
+    let output = transform(input, Options::parse(parse_quote!()).unwrap()).unwrap();
+    let dependency = output.block.stmts[0].to_token_stream().to_string();
+    assert!(dependency.starts_with("const _ : & [u8] = include_bytes !"));
+    assert!(dependency.contains("clone-on-capture.toml"));
+
+    // `capture!` reads the configuration like the attribute.
+    let input: CaptureInput = parse_quote!([dc_b] move || dc_b.len());
+    let expansion = capture(input, Options::parse(parse_quote!()).unwrap())
+        .unwrap()
+        .into_token_stream()
+        .to_string();
+    assert!(expansion.contains("let dc_b = dc_b . duplicate ()"));
+    assert!(expansion.contains("include_bytes !"));
+
+    // Attribute arguments override the file.
+    let expansion = expand(quote!(
+        skip_prefix = "dc_",
+        strategy = "clone",
+        mut_bindings = "move"
+    ))
+    .unwrap();
+    assert!(expansion.contains("let keep_a = {"));
+    assert!(expansion.contains(":: clone_on_capture :: runtime :: capture (& keep_a)"));
+    assert!(!expansion.contains("let dc_b"));
+    assert!(!expansion.contains("let c ="));
+    fs::remove_dir_all(dir).unwrap();
+
+    let dir = crate_dir(
+        "metadata",
+        &[(
+            "Cargo.toml",
+            "[package]\nname = \"foo\"\n\n[package.metadata.clone-on-capture]\nskip_prefix = \"keep_\"\n",
+        )],
+    );
+    let expansion = expand(quote!()).unwrap();
+    assert!(!expansion.contains("let keep_a"));
+    assert!(expansion.contains("let dc_b = {"));
+    assert!(expansion.contains(":: clone_on_capture :: runtime :: capture (& dc_b)"));
+    fs::remove_dir_all(dir).unwrap();
+
+    let dir = crate_dir(
+        "invalid",
+        &[("clone-on-capture.toml", "mut_bindings = \"copy\"\n")],
+    );
+    let error = expand(quote!()).unwrap_err().to_string();
+    assert!(error.contains("invalid clone_on_capture configuration"));
+    assert!(error.contains("unknown `mut_bindings` policy `copy`"));
+
+    fs::write(dir.join("clone-on-capture.toml"), "skip_prefix = \n").unwrap();
+    let error = expand(quote!()).unwrap_err().to_string();
+    assert!(error.contains("clone-on-capture.toml"));
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,172 @@
+use clone_on_capture_core::{analyze, transform, Options};
+use quote::quote;
+use syn::{parse_quote, ItemFn};
+
+fn pretty(item_fn: ItemFn) -> String {
+    prettyplease::unparse(&syn::parse_quote!(#item_fn))
+}
+
+#[test]
+fn analyze_captures() {
+    let input: ItemFn = parse_quote! {
+        fn foo(config: Arc<Config>) {
+            let name = "name".to_string();
+            let mut count = 0;
+            let _closure = move || (name.len(), config.retries, count);
+            let _borrowing = || name.len();
+            let _async = async move { name };
+            drop(config);
+        }
+    };
+
+    let report = analyze(&input);
+    assert_eq!(report.closures.len(), 3);
+
+    let closure = &report.closures[0];
+    assert_eq!(closure.kind, "closure");
+    assert!(closure.rewritten);
+
+    let captures: Vec<(&str, &str)> = closure
+        .captures
+        .iter()
+        .map(|capture| (capture.name.as_str(), capture.strategy.as_str()))
+        .collect();
+    assert_eq!(
+        captures,
+        [("config", "arc"), ("count", "move"), ("name", "clone")]
+    );
+    assert_eq!(closure.capture("count").unwrap().reason, "mut binding");
+
+    let borrowing = &report.closures[1];
+    assert!(!borrowing.rewritten);
+    assert_eq!(borrowing.capture("name").unwrap().strategy, "borrow");
+
+    assert_eq!(report.closures[2].kind, "async");
+}
+
+#[test]
+fn last_use() {
+    let input: ItemFn = parse_quote! {
+        fn foo(first: Rc<String>, second: Rc<String>) {
+            let _closure = move || (first.len(), second.len());
+            drop(second);
+        }
+    };
+
+    let report = analyze(&input);
+    let closure = &report.closures[0];
+    assert_eq!(closure.capture("first").unwrap().strategy, "move");
+    assert_eq!(
+        closure.capture("first").unwrap().reason,
+        "`first` moved (last use of a reference counted binding)"
+    );
+    assert_eq!(closure.capture("second").unwrap().strategy, "rc");
+
+    let options = Options {
+        explain: true,
+        trace_clones: true,
+        ..Options::default()
+    };
+    let output = transform(input, options).unwrap();
+    let output = quote!(#output).to_string();
+    assert!(!output.contains("Rc :: clone (& first)"));
+    assert!(!output.contains("binding : \"first\""));
+    assert!(output.contains("Rc :: clone (& second)"));
+    assert!(output.contains("binding : \"second\""));
+    assert!(!output.contains("clone_on_capture_last_use"));
+    assert!(output.contains("`first` moved (last use of a reference counted binding)"));
+}
+
+#[test]
+fn transform_function() {
+    let input: ItemFn = parse_quote! {
+        fn foo() {
+            let name = "name".to_string();
+            let _closure = move || name.len();
+        }
+    };
+
+    let output = transform(input, Options::default()).unwrap();
+    let expected: ItemFn = parse_quote! {
+        fn foo() {
+            let name = "name".to_string();
+            let _closure = {
+                #[allow(clippy::shadow_reuse, clippy::shadow_same, clippy::shadow_unrelated)]
+                let name = {
+                    #[allow(non_camel_case_types, dead_code)]
+                    struct name {}
+                    ::clone_on_capture::runtime::CheckCapture::<name, 1u32, _>::check(&name);
+                    ::clone_on_capture::runtime::capture(&name)
+                };
+                move || name.len()
+            };
+        }
+    };
+    assert_eq!(pretty(output), pretty(expected));
+}
+
+#[test]
+fn transform_options() {
+    let input: ItemFn = parse_quote! {
+        fn foo() {
+            let name = "name".to_string();
+            let _closure = move || name.len();
+        }
+    };
+
+    let options = Options::parse(parse_quote!(send)).unwrap();
+    let output = transform(input, options).unwrap();
+    assert!(quote!(#output).to_string().contains(
+        ":: clone_on_capture :: runtime :: CheckSend :: < name , 1u32 , _ > :: check (& name)"
+    ));
+
+    let input: ItemFn = parse_quote! {
+        fn foo() {
+            let name = "name".to_string();
+            let _closure = move || name.len();
+        }
+    };
+    let options = Options {
+        trace_clones: true,
+        ..Options::default()
+    };
+    let output = transform(input, options).unwrap();
+    assert!(quote!(#output)
+        .to_string()
+        .contains(":: clone_on_capture :: runtime :: trace :: record"));
+
+    let error = Options::parse(parse_quote!(unknown)).unwrap_err();
+    assert!(error.to_string().contains("unknown"));
+}
+
+#[test]
+fn transform_other_expressions() {
+    // `_` and `const` blocks have no handling of their own, their subexpressions are still
+    // traversed.
+    let input: ItemFn = parse_quote! {
+        fn foo() {
+            let name = "name".to_string();
+            _ = const { 1 } + run(move || name.len());
+            name.len();
+        }
+    };
+
+    let output = transform(input, Options::default()).unwrap();
+    let expected: ItemFn = parse_quote! {
+        fn foo() {
+            let name = "name".to_string();
+            _ = const { 1 } + run({
+                #[allow(clippy::shadow_reuse, clippy::shadow_same, clippy::shadow_unrelated)]
+                let name = {
+                    #[allow(non_camel_case_types, dead_code)]
+                    struct name {}
+                    ::clone_on_capture::runtime::CheckCapture::<name, 1u32, _>::check(&name);
+                    ::clone_on_capture::runtime::capture(&name)
+                };
+                move || name.len()
+            });
+            name.len();
+        }
+    };
+    assert_eq!(pretty(output), pretty(expected));
+}
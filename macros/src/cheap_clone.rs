@@ -0,0 +1,43 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse_quote, Data, DeriveInput, Type};
+
+/// Implements `CheapClone` for a handle type, requiring it of every field type so that
+/// `Handle<T> { inner: Arc<T> }` is cheap for any `T`.
+pub fn derive_cheap_clone_impl(mut input: DeriveInput) -> TokenStream {
+    let fields: Vec<Type> = match &input.data {
+        Data::Struct(data) => data.fields.iter().map(|field| field.ty.clone()).collect(),
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .flat_map(|variant| variant.fields.iter().map(|field| field.ty.clone()))
+            .collect(),
+        Data::Union(data) => data
+            .fields
+            .named
+            .iter()
+            .map(|field| field.ty.clone())
+            .collect(),
+    };
+
+    let ident = input.ident.clone();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+    let this: Type = parse_quote!(#ident #ty_generics);
+
+    let where_clause = input.generics.make_where_clause();
+    where_clause.predicates.push(parse_quote!(#this: Clone));
+    for ty in fields {
+        where_clause
+            .predicates
+            .push(parse_quote!(#ty: ::clone_on_capture::runtime::CheapClone));
+    }
+
+    let (impl_generics, _, where_clause) = input.generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics ::clone_on_capture::runtime::CheapClone for #this
+            #where_clause
+        {
+        }
+    }
+}
@@ -0,0 +1,50 @@
+mod cheap_clone;
+
+use clone_on_capture_core::{capture as capture_impl, transform, CaptureInput, Options};
+use proc_macro::TokenStream;
+use quote::{quote, ToTokens};
+use syn::{
+    parse::Parser, parse_macro_input, punctuated::Punctuated, DeriveInput, ItemFn, Meta, Token,
+};
+
+#[proc_macro_attribute]
+pub fn clone_on_capture(args: TokenStream, item: TokenStream) -> TokenStream {
+    let parser = Punctuated::<Meta, Token![,]>::parse_terminated;
+    let args = parser.parse(args).expect("Failed to parse args");
+    let item = parse_macro_input!(item as ItemFn);
+
+    Options::parse(args)
+        .and_then(|options| transform(item, traced(options)))
+        .map(ToTokens::into_token_stream)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+#[proc_macro]
+pub fn capture(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as CaptureInput);
+
+    // The crate configuration applies like for the attribute without arguments.
+    Options::parse(Punctuated::new())
+        .and_then(|options| capture_impl(input, traced(options)))
+        .map(ToTokens::into_token_stream)
+        .unwrap_or_else(|err| {
+            // A block, so that several errors are still an expression.
+            let errors = err.to_compile_error();
+            quote!({ #errors })
+        })
+        .into()
+}
+
+/// Turns on `trace_clones` with the `trace` feature.
+fn traced(options: Options) -> Options {
+    Options {
+        trace_clones: cfg!(feature = "trace"),
+        ..options
+    }
+}
+
+#[proc_macro_derive(CheapClone)]
+pub fn derive_cheap_clone(item: TokenStream) -> TokenStream {
+    cheap_clone::derive_cheap_clone_impl(parse_macro_input!(item as DeriveInput)).into()
+}
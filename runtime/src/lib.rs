@@ -0,0 +1,269 @@
+//! Runtime support for code generated by the `clone_on_capture` attribute.
+//!
+//! The helpers exist so that the compiler errors for captured bindings are reported on the
+//! binding and explain how to fix them, instead of pointing into generated code.
+
+#[cfg(feature = "trace")]
+pub mod trace;
+
+/// How a value is captured by a move closure, all generated captures go through this trait.
+///
+/// Every `Clone` type is captured by cloning it, which covers `Arc`, `Rc`, `Weak`, shared
+/// references and other handles. Types that are not `Clone` can implement the trait to customise
+/// how they are captured, for example by calling their own `dup()`.
+///
+/// The hint is part of the message as the compiler replaces the label and notes with a
+/// borrowing suggestion, `&T` being `Clone`.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is captured by a move closure but does not implement `Clone`, mark the binding `#[no_clone]` to move it instead, wrap it in `Arc` or implement `Capture` for it"
+)]
+pub trait Capture {
+    /// The value moved into the closure.
+    type Output;
+
+    fn capture(&self) -> Self::Output;
+}
+
+#[diagnostic::do_not_recommend]
+impl<T: Clone> Capture for T {
+    type Output = T;
+
+    #[inline]
+    fn capture(&self) -> T {
+        self.clone()
+    }
+}
+
+/// Captures a binding, called from the prologue generated for each closure.
+#[inline]
+pub fn capture<T: Capture + ?Sized>(value: &T) -> T::Output {
+    value.capture()
+}
+
+/// `Capture` for the binding of a generated marker type `Site`, named like the binding, captured
+/// by the closure at line `LINE`.
+///
+/// A trait bound cannot name the binding, the marker and the line are type parameters so that
+/// the error for a binding that cannot be captured says which one and where. `Capture` is a
+/// supertrait so that the compiler does not report the `capture` call of the binding again.
+#[diagnostic::on_unimplemented(
+    message = "`{Site}` is captured by a move closure at line {LINE}, but `{Self}` does not implement `Clone`",
+    label = "`{Site}` is cloned into the closure",
+    note = "mark the binding `#[no_clone]` to move it instead, wrap it in `Arc` or implement `Capture` for `{Self}`"
+)]
+pub trait CaptureAt<Site, const LINE: u32>: Capture {}
+
+#[diagnostic::do_not_recommend]
+impl<T: Capture + ?Sized, Site, const LINE: u32> CaptureAt<Site, LINE> for T {}
+
+/// Checks that a binding can be captured before the prologue calls `capture` for it. The bound is
+/// on the type, so that the compiler does not suggest borrowing the argument instead.
+pub struct CheckCapture<Site, const LINE: u32, T: CaptureAt<Site, LINE> + ?Sized>(
+    std::marker::PhantomData<fn(&T) -> Site>,
+);
+
+impl<Site, const LINE: u32, T: CaptureAt<Site, LINE> + ?Sized> CheckCapture<Site, LINE, T> {
+    #[inline]
+    pub fn check(_value: &T) {}
+}
+
+/// Reference counted pointers that can be captured as a weak reference, with `weak` in the
+/// binding list of `capture!` or the `weak` strategy of the `types(...)` argument.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is captured as a weak reference, but only `Rc` and `Arc` can be downgraded"
+)]
+pub trait Downgrade {
+    type Weak;
+
+    fn downgrade(&self) -> Self::Weak;
+}
+
+impl<T: ?Sized> Downgrade for std::rc::Rc<T> {
+    type Weak = std::rc::Weak<T>;
+
+    #[inline]
+    fn downgrade(&self) -> Self::Weak {
+        std::rc::Rc::downgrade(self)
+    }
+}
+
+impl<T: ?Sized> Downgrade for std::sync::Arc<T> {
+    type Weak = std::sync::Weak<T>;
+
+    #[inline]
+    fn downgrade(&self) -> Self::Weak {
+        std::sync::Arc::downgrade(self)
+    }
+}
+
+/// Captures a weak reference to a binding.
+#[inline]
+pub fn downgrade<T: Downgrade + ?Sized>(value: &T) -> T::Weak {
+    value.downgrade()
+}
+
+/// Types that can be captured by closures sent to other threads.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is captured by a closure that must be `Send + 'static`, but it is not `Send`",
+    label = "this binding cannot be sent to another thread",
+    note = "use `Arc` instead of `Rc` and `Mutex` instead of `RefCell` for values shared across threads"
+)]
+pub trait SendCapture: Send + 'static {}
+
+#[diagnostic::do_not_recommend]
+impl<T: Send + 'static> SendCapture for T {}
+
+/// Checks a capture of a closure marked `send`, so that a non-`Send` capture is reported on the
+/// binding rather than for the whole closure or future.
+#[inline]
+pub fn assert_send<T: SendCapture>(_value: &T) {}
+
+/// `SendCapture` for the binding of a generated marker type `Site`, named like the binding,
+/// captured by the closure at line `LINE`, like `CaptureAt`.
+#[diagnostic::on_unimplemented(
+    message = "`{Site}` is captured by a closure at line {LINE} that must be `Send + 'static`, but `{Self}` is not `Send`",
+    label = "`{Site}` cannot be sent to another thread",
+    note = "use `Arc` instead of `Rc` and `Mutex` instead of `RefCell` for values shared across threads"
+)]
+pub trait SendCaptureAt<Site, const LINE: u32>: SendCapture {}
+
+#[diagnostic::do_not_recommend]
+impl<T: SendCapture, Site, const LINE: u32> SendCaptureAt<Site, LINE> for T {}
+
+/// Checks a captured binding of a closure marked `send`, the bound is on the type like for
+/// `CheckCapture`.
+pub struct CheckSend<Site, const LINE: u32, T: SendCaptureAt<Site, LINE>>(
+    std::marker::PhantomData<fn(&T) -> Site>,
+);
+
+impl<Site, const LINE: u32, T: SendCaptureAt<Site, LINE>> CheckSend<Site, LINE, T> {
+    #[inline]
+    pub fn check(_value: &T) {}
+}
+
+/// Types that are cheap to clone: reference counted handles, channel senders, shared references
+/// and small `Copy` values.
+///
+/// Closures in `cheap_only` functions may only clone captures that implement this trait. It can be
+/// derived for handle types whose fields are all cheap to clone.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not cheap to clone, but `cheap_only` only allows cheap clones of captures, wrap the value in `Rc` or `Arc`, derive `CheapClone` for handle types or mark the binding `#[no_clone]`"
+)]
+pub trait CheapClone: Clone {}
+
+impl<T: ?Sized> CheapClone for std::rc::Rc<T> {}
+impl<T: ?Sized> CheapClone for std::rc::Weak<T> {}
+impl<T: ?Sized> CheapClone for std::sync::Arc<T> {}
+impl<T: ?Sized> CheapClone for std::sync::Weak<T> {}
+impl<T> CheapClone for std::sync::mpsc::Sender<T> {}
+impl<T> CheapClone for std::sync::mpsc::SyncSender<T> {}
+impl<T: ?Sized> CheapClone for &T {}
+impl<T: ?Sized> CheapClone for std::marker::PhantomData<T> {}
+impl<T: CheapClone> CheapClone for Option<T> {}
+impl<T: CheapClone, const N: usize> CheapClone for [T; N] {}
+
+macro_rules! cheap_clone {
+    ($($ty:ty),* $(,)?) => {
+        $(impl CheapClone for $ty {})*
+    };
+}
+
+cheap_clone!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    std::time::Duration,
+    std::time::Instant,
+    std::time::SystemTime,
+    std::thread::Thread,
+);
+
+macro_rules! cheap_clone_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: CheapClone),+> CheapClone for ($($name,)+) {}
+    };
+}
+
+cheap_clone_tuple!(A);
+cheap_clone_tuple!(A, B);
+cheap_clone_tuple!(A, B, C);
+cheap_clone_tuple!(A, B, C, D);
+
+/// Clones a captured binding in `cheap_only` functions.
+#[inline]
+pub fn cheap_clone_capture<T: CheapClone>(value: &T) -> T {
+    value.clone()
+}
+
+/// A context taken when a closure or async block is created and entered whenever it runs, like
+/// the current `tracing::Span` or a request id.
+///
+/// Functions with `propagate = path::to::hook` call the hook in the prologue of every rewritten
+/// closure and async block, and enter the returned context around each call or poll.
+pub trait Propagate {
+    /// Restores the previous context when dropped.
+    type Guard<'a>
+    where
+        Self: 'a;
+
+    fn enter(&self) -> Self::Guard<'_>;
+}
+
+impl<T: Propagate> Propagate for Option<T> {
+    type Guard<'a>
+        = Option<T::Guard<'a>>
+    where
+        T: 'a;
+
+    fn enter(&self) -> Self::Guard<'_> {
+        self.as_ref().map(Propagate::enter)
+    }
+}
+
+/// Enters a propagated context around the body of a closure.
+#[inline]
+pub fn enter<C: Propagate>(context: &C) -> C::Guard<'_> {
+    context.enter()
+}
+
+/// A future that enters its propagated context around every poll, generated for async blocks.
+pub struct Propagated<C, F> {
+    context: C,
+    future: F,
+}
+
+impl<C, F> Propagated<C, F> {
+    #[inline]
+    pub fn new(context: C, future: F) -> Self {
+        Propagated { context, future }
+    }
+}
+
+impl<C: Propagate, F: std::future::Future> std::future::Future for Propagated<C, F> {
+    type Output = F::Output;
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<F::Output> {
+        // SAFETY: `future` is structurally pinned, it is never moved out of `self` and `Propagated`
+        // has no `Drop` impl. `context` is not pinned and only borrowed.
+        let this = unsafe { self.get_unchecked_mut() };
+        let _guard = this.context.enter();
+        unsafe { std::pin::Pin::new_unchecked(&mut this.future) }.poll(cx)
+    }
+}
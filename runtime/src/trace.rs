@@ -0,0 +1,126 @@
+//! Counts the clones made by generated captures, enabled by the `trace` feature.
+//!
+//! With the feature the prologue of every rewritten closure and async block reports each clone to
+//! a hook, before making it. The default hook adds it to an in-process registry that [`table`]
+//! formats, so closures that clone large values on every call can be found without a profiler.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, PoisonError, RwLock};
+
+/// Where a clone is made: the capture of `binding` by a closure or async block.
+#[derive(Debug)]
+pub struct Site {
+    /// Best effort path of the function, derived from the crate name and the source file.
+    pub function: &'static str,
+    pub file: &'static str,
+    pub line: u32,
+    pub binding: &'static str,
+}
+
+/// Called with the site and the `size_of_val` of the cloned value for every clone.
+pub type Hook = fn(&'static Site, usize);
+
+/// Clones counted at a site by the default hook.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub clones: u64,
+    /// Sum of the `size_of_val` of the cloned values, heap allocations are not included.
+    pub bytes: u64,
+}
+
+static HOOK: RwLock<Hook> = RwLock::new(count);
+
+/// Sites by address, every site is a distinct static.
+static REGISTRY: Mutex<BTreeMap<usize, (&'static Site, Counts)>> = Mutex::new(BTreeMap::new());
+
+/// Replaces the hook, the default one is [`count`].
+pub fn set_hook(hook: Hook) {
+    *HOOK.write().unwrap_or_else(PoisonError::into_inner) = hook;
+}
+
+/// Reports a clone, called from the generated prologue.
+#[inline]
+pub fn record(site: &'static Site, size: usize) {
+    let hook = *HOOK.read().unwrap_or_else(PoisonError::into_inner);
+    hook(site, size);
+}
+
+/// The default hook, adds the clone to the registry.
+pub fn count(site: &'static Site, size: usize) {
+    let mut registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    let (_, counts) = registry
+        .entry(site as *const Site as usize)
+        .or_insert((site, Counts::default()));
+
+    counts.clones += 1;
+    counts.bytes += size as u64;
+}
+
+/// The counted sites, the ones that cloned the most bytes first.
+pub fn counts() -> Vec<(&'static Site, Counts)> {
+    let mut counts: Vec<_> = REGISTRY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .values()
+        .copied()
+        .collect();
+    counts.sort_by(|(a, a_counts), (b, b_counts)| {
+        b_counts
+            .bytes
+            .cmp(&a_counts.bytes)
+            .then(b_counts.clones.cmp(&a_counts.clones))
+            .then((a.file, a.line, a.binding).cmp(&(b.file, b.line, b.binding)))
+    });
+    counts
+}
+
+/// Clears the registry.
+pub fn reset() {
+    REGISTRY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clear();
+}
+
+/// Formats the registry as a table, in the order of [`counts`].
+pub fn table() -> String {
+    let rows: Vec<[String; 5]> = counts()
+        .into_iter()
+        .map(|(site, counts)| {
+            [
+                counts.clones.to_string(),
+                counts.bytes.to_string(),
+                site.binding.to_string(),
+                site.function.to_string(),
+                format!("{}:{}", site.file, site.line),
+            ]
+        })
+        .collect();
+
+    let header = ["clones", "bytes", "binding", "function", "location"].map(str::to_string);
+    let mut widths = header.clone().map(|column| column.len());
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.len());
+        }
+    }
+
+    let mut table = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let _ = writeln!(
+            table,
+            "{:>w0$}  {:>w1$}  {:<w2$}  {:<w3$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            row[4],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+        );
+    }
+    table
+}
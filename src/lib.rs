@@ -1,16 +1,18 @@
-extern crate proc_macro;
+//! Makes all captures of move closures and async blocks clone automatically.
+//!
+//! The attribute is implemented in `clone-on-capture-core` and exposed by
+//! `clone-on-capture-macros`, the code it generates calls into [`runtime`].
 
-mod clone_on_capture;
+pub use clone_on_capture_macros::{capture, clone_on_capture, CheapClone};
+pub use clone_on_capture_runtime as runtime;
+pub use clone_on_capture_runtime::{Capture, CheapClone, Propagate};
 
-use syn::{parse::Parser, parse_macro_input, punctuated::Punctuated, ItemFn, Meta, Token};
-
-use crate::proc_macro::TokenStream;
-
-#[proc_macro_attribute]
-pub fn clone_on_capture(args: TokenStream, item: TokenStream) -> TokenStream {
-    let parser = Punctuated::<Meta, Token![,]>::parse_terminated;
-    let args = parser.parse(args).expect("Failed to parse args");
-    clone_on_capture::clone_on_capture_impl(args, parse_macro_input!(item as ItemFn))
-        .unwrap_or_else(|err| err.to_compile_error())
-        .into()
+/// Marks a closure or async block to be rewritten in `opt_in` mode, like `#[cc]`.
+///
+/// The attribute removes the marker, outside of it this expands to its argument.
+#[macro_export]
+macro_rules! cc {
+    ($closure:expr) => {
+        $closure
+    };
 }
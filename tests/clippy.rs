@@ -0,0 +1,121 @@
+//! Expansions are checked by `cargo clippy --all-targets -- -D warnings`, with the lints that
+//! used to fire on generated clones denied.
+#![deny(
+    clippy::all,
+    clippy::pedantic,
+    clippy::clone_on_ref_ptr,
+    clippy::redundant_clone,
+    clippy::shadow_reuse,
+    clippy::shadow_same,
+    clippy::shadow_unrelated,
+    clippy::let_underscore_untyped
+)]
+
+use clone_on_capture::clone_on_capture;
+use std::rc::Rc;
+use std::sync::Arc;
+
+#[test]
+#[clone_on_capture]
+fn copy_values() {
+    let count = 1;
+    let closure = move || count + 1;
+    assert_eq!(closure(), 2);
+    assert_eq!(count, 1);
+}
+
+#[test]
+#[clone_on_capture]
+fn untyped_reference_counted() {
+    let shared = Rc::new(1);
+    let closure = move || *shared;
+    assert_eq!(closure(), 1);
+    assert_eq!(Rc::strong_count(&shared), 2);
+}
+
+#[test]
+#[clone_on_capture]
+fn typed_reference_counted() {
+    let shared: Rc<u32> = Rc::new(1);
+    let counter: Arc<u32> = Arc::new(2);
+    let closure = move || *shared + *counter;
+    assert_eq!(closure(), 3);
+    assert_eq!(Rc::strong_count(&shared), 2);
+    assert_eq!(Arc::strong_count(&counter), 2);
+}
+
+#[test]
+#[clone_on_capture]
+fn last_use() {
+    let shared: Rc<String> = Rc::new("a".to_string());
+    let name = "name".to_string();
+    // Moved rather than cloned, the closure holds the only reference.
+    let closure = move || Rc::strong_count(&shared) + name.len();
+    assert_eq!(closure(), 5);
+}
+
+#[test]
+#[clone_on_capture(send, explain)]
+#[allow(deprecated)]
+fn send_and_explain() {
+    let name = "name".to_string();
+    let handle = std::thread::spawn(move || name.len());
+    assert_eq!(handle.join().unwrap(), 4);
+    assert_eq!(name.len(), 4);
+}
+
+struct Settings {
+    name: String,
+}
+
+#[test]
+#[clone_on_capture]
+fn field_paths() {
+    let settings: Settings = Settings {
+        name: "name".to_string(),
+    };
+    let closure = move || settings.name.len();
+    assert_eq!(closure(), 4);
+    assert_eq!(settings.name.len(), 4);
+}
+
+fn call_twice(closure: impl Fn() -> usize) -> usize {
+    closure() + closure()
+}
+
+#[test]
+#[clone_on_capture]
+fn last_use_in_nested_closure() {
+    let shared: Rc<String> = Rc::new("a".to_string());
+    let total = call_twice(move || {
+        let inner = move || shared.len();
+        inner()
+    });
+    assert_eq!(total, 2);
+}
+
+#[test]
+#[clone_on_capture]
+fn last_use_after_borrowing_closure() {
+    let shared: Rc<String> = Rc::new("a".to_string());
+    let borrowing = || shared.len();
+    let closure = move || shared.len();
+    assert_eq!(borrowing() + closure(), 2);
+}
+
+#[test]
+#[clone_on_capture]
+fn last_use_after_reference() {
+    let shared: Rc<String> = Rc::new("a".to_string());
+    let reference = &shared;
+    let closure = move || shared.len();
+    assert_eq!(reference.len() + closure(), 2);
+}
+
+#[test]
+#[clone_on_capture]
+fn last_use_in_borrowing_closure() {
+    let shared: Rc<String> = Rc::new("a".to_string());
+    let closures: Vec<_> = (0..3).map(|_| move || shared.len()).collect();
+    assert_eq!(closures.iter().map(|closure| closure()).sum::<usize>(), 3);
+}
@@ -1,3 +1,6 @@
+// `simple_closure_with_format` discards the strings it formats.
+#![allow(unused_must_use, clippy::useless_format)]
+
 use clone_on_capture::clone_on_capture;
 
 #[test]
@@ -69,3 +72,628 @@ fn method_expr() {
         })
         .collect();
 }
+
+#[test]
+#[clone_on_capture(debug(dump = "clone-on-capture-tests"))]
+fn debug_dump() {
+    let a = "a".to_string();
+    let _closure = move || a.len();
+
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .parent()
+        .unwrap()
+        .join("clone-on-capture-tests");
+    let dump = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.to_string_lossy().ends_with("-debug_dump.rs"))
+        .unwrap();
+    let dump = std::fs::read_to_string(dump).unwrap();
+
+    assert!(dump.contains("bindings: `a`"));
+    assert!(dump.contains("clones `a`"));
+    assert!(dump.contains("let a = {"));
+    assert!(dump.contains("::clone_on_capture::runtime::capture(&a)"));
+}
+
+#[test]
+#[clone_on_capture(manifest(path = "clone-on-capture-tests/manifest.jsonl"))]
+fn manifest_records() {
+    let a: Vec<String> = vec![];
+    let dc_b = "b".to_string();
+    let _closure = move || (a.len(), dc_b);
+
+    let manifest = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .parent()
+        .unwrap()
+        .join("clone-on-capture-tests")
+        .join("manifest.jsonl");
+    let manifest = std::fs::read_to_string(manifest).unwrap();
+    let record = manifest
+        .lines()
+        .find(|line| line.contains("\"function\":\"tests::manifest_records\""))
+        .unwrap();
+
+    assert!(record.contains("\"file\":\"tests/tests.rs\""));
+    assert!(record.contains("\"kind\":\"closure\""));
+    assert!(record.contains("{\"name\":\"a\",\"strategy\":\"clone\",\"type\":\"Vec<String>\"}"));
+    assert!(record.contains("{\"name\":\"dc_b\",\"strategy\":\"move\",\"type\":null}"));
+}
+
+#[test]
+#[clone_on_capture(lint, allow(redundant_clone, clone_in_loop))]
+fn lint_allow() {
+    let a = "a".to_string();
+    for _ in 0..2 {
+        let _closure = move || a.len();
+    }
+    let _closure = move || a.len();
+}
+
+#[test]
+#[clone_on_capture]
+fn for_loop_body() {
+    let a = "a".to_string();
+    for _ in 0..2 {
+        let closure = move || a;
+        closure();
+    }
+    let _b = a;
+}
+
+#[test]
+#[clone_on_capture]
+fn no_clone_marker() {
+    #[no_clone]
+    let a = "a".to_string();
+    let a_address = a.as_ptr();
+
+    let closure = move || {
+        let b = a;
+        assert_eq!(a_address, b.as_ptr());
+    };
+
+    closure();
+}
+
+#[test]
+#[clone_on_capture(send)]
+fn send_captures() {
+    let a = std::sync::Arc::new("a".to_string());
+    let handle = std::thread::spawn(move || a.len());
+    assert_eq!(handle.join().unwrap(), a.len());
+}
+
+#[derive(Clone, clone_on_capture::CheapClone)]
+struct Handle<T> {
+    inner: std::sync::Arc<T>,
+}
+
+#[test]
+#[clone_on_capture(cheap_only)]
+fn cheap_only_captures() {
+    let handle = Handle {
+        inner: std::sync::Arc::new("a".to_string()),
+    };
+    let count = 1;
+    let closure = move || handle.inner.len() + count;
+    assert_eq!(closure(), handle.inner.len() + count);
+}
+
+struct Descriptor(u32);
+
+impl Descriptor {
+    fn dup(&self) -> Descriptor {
+        Descriptor(self.0 + 1)
+    }
+}
+
+impl clone_on_capture::Capture for Descriptor {
+    type Output = Descriptor;
+
+    fn capture(&self) -> Descriptor {
+        self.dup()
+    }
+}
+
+#[test]
+#[clone_on_capture]
+fn custom_capture() {
+    let fd = Descriptor(1);
+    let closure = move || {
+        let Descriptor(number) = fd;
+        number
+    };
+    assert_eq!(closure(), 2);
+    assert_eq!(fd.0, 1);
+}
+
+struct Token(u32);
+
+impl Token {
+    fn renew(&self) -> Token {
+        Token(self.0 * 10)
+    }
+}
+
+#[test]
+fn type_strategies() {
+    typed_captures(std::rc::Rc::new(1), "name");
+}
+
+#[clone_on_capture(types(Token = "renew"))]
+fn typed_captures(shared: std::rc::Rc<u32>, name: &str) {
+    let counter: std::sync::Arc<u32> = std::sync::Arc::new(0);
+    let token: Token = Token(1);
+    let count: u32 = 3;
+
+    let closure = move || (*shared, name.len(), *counter, token.0, count);
+
+    assert_eq!(closure(), (1, 4, 0, 10, 3));
+    assert_eq!(std::rc::Rc::strong_count(&shared), 2);
+    assert_eq!(std::sync::Arc::strong_count(&counter), 2);
+    assert_eq!(token.0, 1);
+}
+
+struct State {
+    name: String,
+    config: Config,
+    log: Vec<String>,
+}
+
+struct Config {
+    retries: Vec<u32>,
+}
+
+struct Service {
+    state: State,
+}
+
+impl Service {
+    #[clone_on_capture]
+    fn retries(&self) -> impl Fn() -> usize {
+        move || self.state.config.retries.len()
+    }
+}
+
+#[test]
+#[clone_on_capture]
+fn field_paths() {
+    let state: State = State {
+        name: "name".to_string(),
+        config: Config {
+            retries: vec![1, 2],
+        },
+        log: vec!["started".to_string()],
+    };
+    let log_address = state.log.as_ptr();
+
+    let closure = move || {
+        let nested = move || state.config.retries.len();
+        (state.name.len(), nested(), state.log.as_ptr())
+    };
+
+    let (name, retries, log) = closure();
+    assert_eq!((name, retries), (4, 2));
+    assert_ne!(log, log_address);
+    drop(state);
+
+    let service = Service {
+        state: State {
+            name: "service".to_string(),
+            config: Config { retries: vec![1] },
+            log: vec![],
+        },
+    };
+    let retries = service.retries();
+    drop(service);
+    assert_eq!(retries(), 1);
+}
+
+struct Worker {
+    name: String,
+}
+
+impl Worker {
+    fn name(&self) -> usize {
+        self.name.len()
+    }
+
+    #[clone_on_capture]
+    fn run(self: std::sync::Arc<Self>) -> usize {
+        let closure = move || self.name();
+        closure() + self.name()
+    }
+
+    #[clone_on_capture]
+    fn run_borrowed(&self) -> usize {
+        let closure = move || self.name();
+        closure() + self.name()
+    }
+}
+
+#[test]
+fn whole_self() {
+    let worker = std::sync::Arc::new(Worker {
+        name: "name".to_string(),
+    });
+    assert_eq!(std::sync::Arc::clone(&worker).run(), 8);
+    assert_eq!(worker.run_borrowed(), 8);
+    assert_eq!(std::sync::Arc::strong_count(&worker), 1);
+}
+
+struct Joined {
+    b_c: String,
+    b: Split,
+}
+
+struct Split {
+    c: String,
+}
+
+#[test]
+#[clone_on_capture]
+fn field_paths_with_underscores() {
+    let a: Joined = Joined {
+        b_c: "1".to_string(),
+        b: Split {
+            c: "22".to_string(),
+        },
+    };
+    let a_b: Split = Split {
+        c: "22".to_string(),
+    };
+
+    let closure = move || (a.b_c.len(), a_b.c.len(), a.b.c.len());
+    assert_eq!(closure(), (1, 2, 2));
+    assert_eq!((a.b_c.len(), a_b.c.len()), (1, 2));
+}
+
+struct Counter {
+    count: std::cell::RefCell<u32>,
+}
+
+#[test]
+#[clone_on_capture]
+fn field_paths_through_rc() {
+    let counter = std::rc::Rc::new(Counter {
+        count: std::cell::RefCell::new(0),
+    });
+
+    let closure = move || *counter.count.borrow_mut() += 1;
+    closure();
+    closure();
+    assert_eq!(*counter.count.borrow(), 2);
+}
+
+#[test]
+#[clone_on_capture(promote)]
+#[allow(deprecated)]
+fn promote_closures() {
+    let name = "name".to_string();
+    let mut count = 0;
+
+    let mut add = || count += name.len();
+    add();
+    add();
+
+    let handle = std::thread::spawn(|| name.len());
+    assert_eq!(handle.join().unwrap(), 4);
+    assert_eq!((count, name.len()), (8, 4));
+}
+
+#[test]
+#[clone_on_capture]
+fn promote_marker() {
+    let name = "name".to_string();
+    let handle = std::thread::spawn(
+        #[promote]
+        || name.len(),
+    );
+    assert_eq!(handle.join().unwrap(), 4);
+    assert_eq!(name.len(), 4);
+}
+
+#[test]
+#[clone_on_capture(opt_in)]
+fn opt_in_closures() {
+    let a = "a".to_string();
+    let b = "b".to_string();
+    let c = "c".to_string();
+    let c_address = c.as_ptr();
+
+    let marked = #[cc]
+    move || a.len();
+    let wrapped = clone_on_capture::cc!(move || b.len());
+    let unmarked = move || c;
+
+    assert_eq!(marked() + wrapped(), 2);
+    let c = unmarked();
+    assert_eq!(c.as_ptr(), c_address);
+    assert_eq!(a.len() + b.len(), 2);
+}
+
+type Handler = Box<dyn Fn() -> usize>;
+
+struct Button {
+    handlers: Vec<Handler>,
+}
+
+impl Button {
+    fn connect_clicked(&mut self, handler: impl Fn() -> usize + 'static) {
+        self.handlers.push(Box::new(handler));
+    }
+}
+
+#[test]
+#[clone_on_capture(targets(thread::spawn, .connect_clicked))]
+fn call_targets() {
+    let name = "name".to_string();
+    let other = "other".to_string();
+    let other_address = other.as_ptr();
+
+    let handle = std::thread::spawn(move || name.len());
+    assert_eq!(handle.join().unwrap(), 4);
+
+    let mut button = Button { handlers: vec![] };
+    button.connect_clicked(move || name.len());
+    assert_eq!(button.handlers[0](), 4);
+
+    let moved = move || other;
+    let other = moved();
+    assert_eq!(other.as_ptr(), other_address);
+    assert_eq!(name.len(), 4);
+}
+
+thread_local! {
+    static REQUEST: std::cell::Cell<u32> = const { std::cell::Cell::new(0) };
+}
+
+struct RequestId(u32);
+
+struct RequestGuard(u32);
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        REQUEST.set(self.0);
+    }
+}
+
+impl clone_on_capture::Propagate for RequestId {
+    type Guard<'a> = RequestGuard;
+
+    fn enter(&self) -> RequestGuard {
+        RequestGuard(REQUEST.replace(self.0))
+    }
+}
+
+fn current_request() -> RequestId {
+    RequestId(REQUEST.get())
+}
+
+#[test]
+#[clone_on_capture(propagate = current_request)]
+fn propagate_context() {
+    REQUEST.set(7);
+
+    let handle = std::thread::spawn(move || REQUEST.get());
+    assert_eq!(handle.join().unwrap(), 7);
+
+    let future = async move { REQUEST.get() };
+    REQUEST.set(1);
+
+    let mut future = std::pin::pin!(future);
+    let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    let poll = std::future::Future::poll(future.as_mut(), &mut cx);
+    assert_eq!(poll, std::task::Poll::Ready(7));
+    assert_eq!(REQUEST.get(), 1);
+}
+
+struct Schema {
+    tables: Vec<String>,
+}
+
+#[test]
+#[clone_on_capture(shared(table))]
+fn shared_declarations() {
+    #[shared]
+    let schema = Schema {
+        tables: vec!["users".to_string()],
+    };
+    #[shared(local)]
+    let names: Vec<String> = vec!["a".to_string()];
+    let table = vec![1, 2, 3];
+
+    let closure = move || (schema.tables.len(), names.len(), table.len());
+    assert_eq!(closure(), (1, 1, 3));
+    assert_eq!(std::sync::Arc::strong_count(&schema), 2);
+    assert_eq!(std::rc::Rc::strong_count(&names), 2);
+    assert_eq!(std::sync::Arc::strong_count(&table), 2);
+    assert_eq!(schema.tables[0], "users");
+}
+
+#[test]
+#[clone_on_capture]
+fn shared_mut_declarations() {
+    #[shared_mut]
+    let mut count = 0;
+    #[shared_mut]
+    let mut log: Vec<String> = Vec::new();
+
+    let increment = move || {
+        count += 1;
+        log.push(format!("increment {}", count));
+    };
+    let reset = move || count = 0;
+
+    increment();
+    increment();
+    assert_eq!(count, 2);
+    reset();
+    increment();
+    count = count + 10;
+    log.push(format!("{}", log.len()));
+    assert_eq!(count, 11);
+    assert_eq!(log, ["increment 1", "increment 2", "increment 1", "3"]);
+}
+
+trait Reset {
+    fn reset(&mut self);
+}
+
+impl Reset for Vec<String> {
+    fn reset(&mut self) {
+        self.clear();
+    }
+}
+
+#[test]
+#[clone_on_capture]
+fn shared_mut_method_calls() {
+    #[shared_mut]
+    let mut items: Vec<String> = vec!["a".to_string()];
+    #[shared_mut(send)]
+    let mut locked: Vec<u32> = vec![1, 2];
+
+    let count = items.len() + items.len();
+    let total = locked.len() + locked.iter().sum::<u32>() as usize;
+    items.push(items.len().to_string());
+    let names = items.iter().map(String::clone).collect::<Vec<_>>();
+    assert_eq!(
+        (count, total, names),
+        (2, 5, vec!["a".to_string(), "1".to_string()])
+    );
+
+    #[borrow_mut]
+    items.reset();
+    assert!(items.is_empty() && !locked.is_empty());
+}
+
+#[test]
+#[clone_on_capture]
+fn shared_mut_send() {
+    #[shared_mut(send)]
+    let mut total = 0;
+
+    let handles: Vec<_> = (1..=4)
+        .map(|i| std::thread::spawn(move || total += i))
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(total, 10);
+}
+
+macro_rules! on_drop {
+    ($name:ident, $log:ident) => {
+        clone_on_capture::capture!([$name, weak $log] move || {
+            if let Some(log) = $log.upgrade() {
+                log.borrow_mut().push($name.len());
+            }
+        })
+    };
+}
+
+#[test]
+fn capture_macro() {
+    let name = "name".to_string();
+    let name_address = name.as_ptr();
+    let log = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+
+    let closure = clone_on_capture::capture!([name] move || {
+        assert_ne!(name.as_ptr(), name_address);
+        name.len()
+    });
+    assert_eq!(closure(), 4);
+
+    let record = on_drop!(name, log);
+    assert_eq!(std::rc::Rc::strong_count(&log), 1);
+    record();
+    assert_eq!(*log.borrow(), [4]);
+
+    drop(log);
+    record();
+    assert_eq!(name, "name");
+}
+
+#[test]
+#[clone_on_capture(skip(moved))]
+fn skip_list() {
+    let moved = "moved".to_string();
+    let cloned = "cloned".to_string();
+    let moved_address = moved.as_ptr();
+    let cloned_address = cloned.as_ptr();
+
+    let closure = move || {
+        assert_eq!(moved.as_ptr(), moved_address);
+        assert_ne!(cloned.as_ptr(), cloned_address);
+    };
+
+    closure();
+    assert_eq!(cloned, "cloned");
+}
+
+#[test]
+#[clone_on_capture(only(config))]
+fn only_list() {
+    let config = "config".to_string();
+    let buffer = "buffer".to_string();
+    let buffer_address = buffer.as_ptr();
+
+    let closure = move || {
+        assert_eq!(buffer.as_ptr(), buffer_address);
+        config.len()
+    };
+
+    assert_eq!(closure(), 6);
+    assert_eq!(config, "config");
+}
+
+macro_rules! hygienic_function {
+    ($name:ident, $value:ident) => {
+        #[test]
+        #[clone_on_capture]
+        fn $name() {
+            let $value = "caller".to_string();
+            let value = vec![1, 2];
+
+            let closure = move || ($value.len(), value.len());
+            assert_eq!(closure(), (6, 2));
+            assert_eq!(($value, value), ("caller".to_string(), vec![1, 2]));
+        }
+    };
+}
+
+hygienic_function!(hygienic_bindings, value);
+
+mod config {
+    pub fn load() -> usize {
+        1
+    }
+}
+
+struct Settings;
+
+impl Settings {
+    fn config() -> usize {
+        2
+    }
+}
+
+/// Not `Clone`, so capturing it would not compile.
+struct Connection(usize);
+
+#[test]
+#[clone_on_capture]
+fn item_paths() {
+    let config = Connection(3);
+
+    let closure = move || config::load() + Settings::config() + <Settings>::config();
+    assert_eq!(closure(), 5);
+
+    let closure = move || assert_eq!(config::load(), 1);
+    closure();
+
+    assert_eq!(config.0, 3);
+}
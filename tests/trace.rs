@@ -0,0 +1,41 @@
+use clone_on_capture::clone_on_capture;
+use clone_on_capture::runtime::trace;
+
+#[test]
+#[clone_on_capture]
+fn trace_counts() {
+    let name = "name".to_string();
+    let values = [0u64; 16];
+    let shared = std::rc::Rc::new(1);
+
+    for _ in 0..3 {
+        let closure = move || (name.len(), values.len(), *shared);
+        assert_eq!(closure(), (4, 16, 1));
+    }
+
+    let counts = trace::counts();
+    let count = |binding| {
+        counts
+            .iter()
+            .find(|(site, _)| site.binding == binding)
+            .map(|(_, counts)| *counts)
+    };
+
+    assert_eq!(
+        count("values"),
+        Some(trace::Counts {
+            clones: 3,
+            bytes: 3 * 128
+        })
+    );
+    assert_eq!(count("name").map(|counts| counts.clones), Some(3));
+    assert_eq!(count("shared").map(|counts| counts.clones), Some(3));
+    assert_eq!(counts[0].0.binding, "values");
+    assert_eq!(counts[0].0.function, "trace::trace_counts");
+    assert_eq!(counts[0].0.file, "tests/trace.rs");
+
+    let table = trace::table();
+    let mut lines = table.lines();
+    assert!(lines.next().unwrap().starts_with("clones  bytes  binding"));
+    assert!(lines.next().unwrap().starts_with("     3    384  values "));
+}
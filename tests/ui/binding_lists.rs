@@ -0,0 +1,21 @@
+use clone_on_capture::clone_on_capture;
+
+#[clone_on_capture(skip(tx, renamed), only(state, cfg))]
+fn lists() {
+    let tx = std::sync::mpsc::channel::<u8>().0;
+    let state = "state".to_string();
+    let _closure = move || (tx.send(1), state.len());
+}
+
+struct Message {
+    tx: u8,
+}
+
+// A struct literal member is not a binding.
+#[clone_on_capture(skip(tx))]
+fn member_only() -> u8 {
+    let message = Message { tx: 1 };
+    message.tx
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use clone_on_capture::capture;
+
+fn main() {
+    let a = "a".to_string();
+    let b = "b".to_string();
+
+    let _unused = capture!([a, b, weak c] move || a.len());
+    let _not_move = capture!([a] || a.len());
+    let _twice = capture!([a, a] move || a.len());
+}
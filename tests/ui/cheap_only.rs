@@ -0,0 +1,10 @@
+use clone_on_capture::clone_on_capture;
+use std::sync::Arc;
+
+#[clone_on_capture(cheap_only)]
+fn main() {
+    let shared = Arc::new(1);
+    let count = 2;
+    let name = "name".to_string();
+    let _closure = move || (*shared + count, name.len());
+}
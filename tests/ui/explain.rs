@@ -0,0 +1,15 @@
+#![deny(deprecated)]
+
+use clone_on_capture::clone_on_capture;
+
+#[clone_on_capture(explain)]
+fn main() {
+    let a = "a".to_string();
+    let dc_b = "b".to_string();
+    let mut c = 0;
+    let _closure = move || {
+        c += 1;
+        (a.len(), dc_b, c)
+    };
+    let _borrowing = || a.len();
+}
@@ -0,0 +1,29 @@
+#![deny(deprecated)]
+
+use clone_on_capture::clone_on_capture;
+
+macro_rules! ignore {
+    ($($tokens:tt)*) => {
+        ()
+    };
+}
+
+#[clone_on_capture(lint)]
+fn main() {
+    let a = "a".to_string();
+    let _redundant = move || a.len();
+
+    let dc_b = 1;
+    let _moved = move || dc_b + 1;
+    let _ = dc_b;
+
+    let c = "c".to_string();
+    for _ in 0..2 {
+        let _in_loop = move || c.len();
+    }
+
+    let d = "d".to_string();
+    let _scanned = move || ignore!(d => d);
+
+    let _ = (c, d);
+}
@@ -0,0 +1,11 @@
+use clone_on_capture::clone_on_capture;
+
+struct DbConn;
+
+#[clone_on_capture]
+fn main() {
+    let conn = DbConn;
+    let _closure = move || {
+        let _conn = &conn;
+    };
+}
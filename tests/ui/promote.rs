@@ -0,0 +1,23 @@
+#![deny(deprecated)]
+
+use clone_on_capture::clone_on_capture;
+
+#[clone_on_capture(promote)]
+fn borrowed() {
+    let name = "name".to_string();
+    let mut count = 0;
+    let mut add = || count += name.len();
+    add();
+}
+
+#[clone_on_capture(promote)]
+fn borrowed_in_macro() {
+    let mut count = 0;
+    let mut add = || {
+        count += 1;
+        println!("{}", count);
+    };
+    add();
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use clone_on_capture::clone_on_capture;
+use std::rc::Rc;
+use std::sync::Arc;
+
+#[clone_on_capture]
+fn main() {
+    let shared = Arc::new(1);
+    let local = Rc::new(2);
+    let handle = std::thread::spawn(#[send] move || *shared + *local);
+    let _ = handle.join();
+}
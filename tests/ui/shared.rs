@@ -0,0 +1,14 @@
+use clone_on_capture::clone_on_capture;
+
+#[clone_on_capture]
+fn mutated() {
+    #[shared]
+    let config = vec![1];
+    let _closure = move || config.len();
+    config[0] = 2;
+}
+
+#[clone_on_capture(shared(missing))]
+fn unknown_name() {}
+
+fn main() {}